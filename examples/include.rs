@@ -1,4 +1,4 @@
-use minicdn::{include_mini_cdn, release_include_mini_cdn, MiniCdn};
+use minicdn::{include_mini_cdn, release_include_mini_cdn, Encoding, MiniCdn};
 
 pub fn main() {
     println!("Filesystem:");
@@ -9,37 +9,54 @@ pub fn main() {
 
     println!("Conditional:");
     dump_mini_cdn(release_include_mini_cdn!("./tree"));
+
+    #[cfg(feature = "fingerprint")]
+    {
+        println!("Fingerprinted:");
+        let mini_cdn = release_include_mini_cdn!(
+            "./tree",
+            fingerprint = true,
+            fingerprint_keep_original = true
+        );
+        println!("{:?}", mini_cdn.fingerprinted("lorem_ipsum.txt"));
+        println!(
+            "{}",
+            minicdn::release_asset!("./tree", "lorem_ipsum.txt", fingerprint = true)
+        );
+        dump_mini_cdn(mini_cdn);
+    }
+
+    #[cfg(feature = "rewrite")]
+    {
+        println!("Rewritten:");
+        let mini_cdn =
+            release_include_mini_cdn!("./tree", fingerprint = true, fingerprint_rewrite = true);
+        let index = mini_cdn.get("index.html").unwrap();
+        println!("{}", String::from_utf8_lossy(index.contents()));
+        dump_mini_cdn(mini_cdn);
+    }
+
+    #[cfg(feature = "lazy")]
+    {
+        println!("Lazy:");
+        let mini_cdn = MiniCdn::Embedded(include_mini_cdn!("./tree", lazy = true));
+        let text = mini_cdn.get("lorem_ipsum.txt").unwrap();
+        assert!(text.contents.is_empty());
+        println!("{}", String::from_utf8_lossy(&text.contents()[..26]));
+        dump_mini_cdn(mini_cdn);
+    }
 }
 
 fn dump_mini_cdn(mini_cdn: MiniCdn) {
     let mut total_size = 0;
     mini_cdn.for_each(|path, file| {
         println!("{:?}: {:?}", path, file);
-        total_size += file.contents.len();
-        #[cfg(feature = "brotli")]
-        {
-            total_size += file
-                .contents_brotli
-                .as_ref()
-                .map(|c| c.len())
-                .unwrap_or_default();
-        }
-        #[cfg(feature = "gzip")]
-        {
-            total_size += file
-                .contents_gzip
-                .as_ref()
-                .map(|c| c.len())
-                .unwrap_or_default();
-        }
-        #[cfg(feature = "webp")]
-        {
-            total_size += file
-                .contents_webp
-                .as_ref()
-                .map(|c| c.len())
-                .unwrap_or_default();
-        }
+        total_size += file.contents().len();
+        total_size += [Encoding::Brotli, Encoding::Gzip, Encoding::Webp]
+            .into_iter()
+            .filter_map(|encoding| file.encoded(encoding))
+            .map(<[u8]>::len)
+            .sum::<usize>();
     });
 
     #[cfg(feature = "serde")]
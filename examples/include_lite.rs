@@ -1,39 +1,37 @@
-use minicdn::{include_mini_cdn, MiniCdn};
+use minicdn::{include_mini_cdn, include_mini_cdn_module, Encoding, MiniCdn, MiniCdnFile};
+
+include_mini_cdn_module!(mod tree, "./tree");
 
 pub fn main() {
     println!("Included:");
     dump_mini_cdn(MiniCdn::Embedded(include_mini_cdn!("./tree")));
+
+    println!("Module:");
+    let some_binary: &'static MiniCdnFile = tree::subtree::SOME_BINARY_BIN;
+    println!("{:?}", some_binary);
+    dump_mini_cdn(MiniCdn::Embedded(tree::mini_cdn()));
+
+    #[cfg(feature = "phf")]
+    {
+        static TREE: &minicdn::StaticMiniCdn = minicdn::include_mini_cdn_static!("./tree");
+
+        println!("Static:");
+        let some_binary: &'static MiniCdnFile = TREE.get("subtree/some_binary.bin").unwrap();
+        println!("{:?}", some_binary);
+        dump_mini_cdn(MiniCdn::Static(TREE));
+    }
 }
 
 fn dump_mini_cdn(mini_cdn: MiniCdn) {
     let mut total_size = 0;
     mini_cdn.for_each(|path, file| {
         println!("{:?}: {:?}", path, file);
-        total_size += file.contents.len();
-        #[cfg(feature = "brotli")]
-        {
-            total_size += file
-                .contents_brotli
-                .as_ref()
-                .map(|c| c.len())
-                .unwrap_or_default();
-        }
-        #[cfg(feature = "gzip")]
-        {
-            total_size += file
-                .contents_gzip
-                .as_ref()
-                .map(|c| c.len())
-                .unwrap_or_default();
-        }
-        #[cfg(feature = "webp")]
-        {
-            total_size += file
-                .contents_webp
-                .as_ref()
-                .map(|c| c.len())
-                .unwrap_or_default();
-        }
+        total_size += file.contents().len();
+        total_size += [Encoding::Brotli, Encoding::Gzip, Encoding::Webp]
+            .into_iter()
+            .filter_map(|encoding| file.encoded(encoding))
+            .map(<[u8]>::len)
+            .sum::<usize>();
     });
 
     #[cfg(feature = "serde")]
@@ -0,0 +1,10 @@
+/*! script.js | MIT License */
+
+// Greets the visitor.
+function greet(name) {
+    const greeting = "Hello, " + name + "!";
+    console.log(greeting);
+    return greeting;
+}
+
+greet("world");
@@ -0,0 +1,593 @@
+//! Compresses files from a build script, for use on stable Rust (the macros in `minicdn`
+//! require nightly).
+//!
+//! ```ignore
+//! // build.rs
+//! fn main() {
+//!     minicdn_build::embed("./public", "public.rs");
+//! }
+//!
+//! // main.rs
+//! let files = minicdn::MiniCdn::Embedded(include!(concat!(env!("OUT_DIR"), "/public.rs")));
+//! ```
+//!
+//! The generated code depends on which fields [`minicdn_core::MiniCdnFile`] has, so it only
+//! compiles if the same features are enabled on `minicdn_build` as on `minicdn`.
+
+pub use minicdn_core::CompressOptions;
+use minicdn_core::EmbeddedMiniCdn;
+#[cfg(feature = "encoder")]
+pub use minicdn_core::Encoder;
+#[cfg(feature = "transform")]
+pub use minicdn_core::{CommandTransform, Transform, TransformInput, TransformOutput};
+use proc_macro2::{Ident, Literal, Span, TokenTree};
+use quote::{quote, ToTokens, TokenStreamExt};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Compresses the files in `root_path` (relative to the package root) and writes an expression
+/// of type `EmbeddedMiniCdn` to `file_name` in `OUT_DIR`, to be used with `include!`.
+///
+/// Also tells Cargo to rerun the build script if any of the files change. If the
+/// `MINICDN_REPORT` environment variable is set, warns about how much deduplicating identical
+/// files saved and which parameters `auto` chose.
+pub fn embed(root_path: &str, file_name: &str) {
+    embed_with_options(root_path, file_name, &CompressOptions::default())
+}
+
+/// Like [`embed`], but with additional options.
+pub fn embed_with_options(root_path: &str, file_name: &str, options: &CompressOptions) {
+    let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR not set (not in a build script?)");
+    // `include!` resolves paths relative to the generated file, so they must be absolute.
+    let root_path = Path::new(root_path)
+        .canonicalize()
+        .unwrap_or_else(|e| panic!("failed to canonicalize path {:?}: {}", root_path, e));
+    let root_path = root_path
+        .to_str()
+        .unwrap_or_else(|| panic!("failed to stringify path {:?}", root_path));
+
+    // Catches new files.
+    println!("cargo:rerun-if-changed={}", root_path);
+    println!("cargo:rerun-if-env-changed={}", REPORT_VAR);
+
+    let compressed = EmbeddedMiniCdn::compress(root_path, &options.build_time());
+    compressed.report.iter().for_each(|message| report(message));
+    let mini_cdn = compressed.mini_cdn;
+    let tokens = quote_embedded_mini_cdn(
+        root_path,
+        &mini_cdn,
+        &Path::new(&out_dir).join(file_name).to_string_lossy(),
+        &mut |path| {
+            println!("cargo:rerun-if-changed={}", path);
+        },
+        &mut report,
+    );
+    let out_path = Path::new(&out_dir).join(file_name);
+    std::fs::write(&out_path, tokens.to_string())
+        .unwrap_or_else(|e| panic!("failed to write {:?}: {}", out_path, e));
+}
+
+/// Shows a message to the user as a warning, since Cargo hides other output of build scripts,
+/// if they asked for it.
+fn report(message: &str) {
+    if reporting() {
+        println!("cargo:warning={}", message);
+    }
+}
+
+/// The environment variable that, if set, makes the macros and [`embed`] report what they did.
+const REPORT_VAR: &str = "MINICDN_REPORT";
+
+/// Whether the user asked for reports (e.g. of how much deduplication saved), which would
+/// otherwise be noise in every build.
+#[doc(hidden)]
+pub fn reporting() -> bool {
+    std::env::var_os(REPORT_VAR).is_some()
+}
+
+/// An expression of type `EmbeddedMiniCdn` containing the compressed files, with blobs
+/// identified by `key` (see [`Blobs::new`]). `report` is called with the [`Blobs::report`], if
+/// any.
+#[doc(hidden)]
+pub fn quote_embedded_mini_cdn(
+    root_path: &str,
+    mini_cdn: &EmbeddedMiniCdn,
+    key: &str,
+    track: &mut dyn FnMut(&str),
+    report: &mut dyn FnMut(&str),
+) -> proc_macro2::TokenStream {
+    let mut blobs = Blobs::new(key);
+    let files = embed_files(root_path, mini_cdn, &mut blobs, track);
+    if let Some(message) = blobs.report(root_path) {
+        report(&message);
+    }
+    let files = files.into_iter().map(|EmbeddedFile { paths, file, .. }| {
+        quote! {
+            {
+                let file = #file;
+                #(ret.insert(std::borrow::Cow::Borrowed(#paths), file.clone());)*
+            }
+        }
+    });
+    let manifest = quote_manifest(mini_cdn);
+
+    quote! {
+        {
+            #blobs
+            let mut ret = minicdn::EmbeddedMiniCdn::default();
+            #(#files)*
+            #manifest
+            ret
+        }
+    }
+}
+
+/// A file, ready to be embedded.
+#[doc(hidden)]
+pub struct EmbeddedFile<'a> {
+    /// The path of the file within the root directory.
+    pub original: &'a str,
+    /// The paths the file is served at.
+    pub paths: Vec<&'a str>,
+    /// An expression of type `MiniCdnFile`, which is constant unless it has encoded variants.
+    pub file: proc_macro2::TokenStream,
+}
+
+/// Converts compressed files to constant expressions, sorted by original path. Files that are
+/// served at multiple paths (e.g. both original and fingerprinted) are only embedded once.
+///
+/// `track` is called with the absolute path of each file, or file that affected them. The files
+/// reference `blobs`.
+#[doc(hidden)]
+pub fn embed_files<'a>(
+    root_path: &str,
+    mini_cdn: &'a EmbeddedMiniCdn,
+    blobs: &mut Blobs,
+    track: &mut dyn FnMut(&str),
+) -> Vec<EmbeddedFile<'a>> {
+    let mut files = BTreeMap::<&str, EmbeddedFile>::new();
+    // Absolute paths of files on disk that are included by the embedded files.
+    let mut included = HashSet::<String>::new();
+
+    // Fingerprinted path to original path.
+    let originals = mini_cdn
+        .manifest()
+        .map(|(original, fingerprinted)| (fingerprinted, original))
+        .collect::<HashMap<_, _>>();
+
+    // The map's order differs between builds, but it decides which blob is which, and the
+    // output should be reproducible.
+    let mut sorted = mini_cdn.iter().collect::<Vec<_>>();
+    sorted.sort_unstable_by_key(|&(path, _)| path);
+
+    for (path, file) in sorted {
+        let path = &**path;
+        let original = originals.get(path).copied().unwrap_or(path);
+
+        if let Some(embedded) = files.get_mut(original) {
+            embedded.paths.push(path);
+            embedded.paths.sort_unstable();
+            continue;
+        }
+
+        #[allow(unused_mut)]
+        let mut fields = Vec::<proc_macro2::TokenStream>::new();
+
+        #[allow(unused)]
+        use std::ops::Deref;
+
+        #[cfg(feature = "etag")]
+        {
+            let etag = file.etag.deref();
+            fields.push(quote! {
+                etag: minicdn::ByteString::from_static(#etag)
+            });
+        }
+
+        #[cfg(feature = "last_modified")]
+        {
+            let last_modified = file.last_modified.deref();
+            fields.push(quote! {
+                last_modified: minicdn::ByteString::from_static(#last_modified)
+            });
+        }
+
+        #[cfg(feature = "mime")]
+        {
+            let mime = file.mime.deref();
+            fields.push(quote! {
+                mime: minicdn::ByteString::from_static(#mime)
+            });
+        }
+
+        #[cfg(feature = "brotli")]
+        {
+            let contents_brotli = blobs.quote_option(&file.contents_brotli);
+            fields.push(quote! {
+                contents_brotli: #contents_brotli
+            });
+        }
+
+        #[cfg(feature = "gzip")]
+        {
+            let contents_gzip = blobs.quote_option(&file.contents_gzip);
+            fields.push(quote! {
+                contents_gzip: #contents_gzip
+            });
+        }
+
+        #[cfg(feature = "webp")]
+        {
+            let contents_webp = blobs.quote_option(&file.contents_webp);
+            fields.push(quote! {
+                contents_webp: #contents_webp
+            });
+        }
+
+        #[cfg(feature = "config")]
+        {
+            let headers = file.headers.iter().map(|(name, value)| {
+                let name = name.deref();
+                let value = value.deref();
+                quote! {
+                    (std::borrow::Cow::Borrowed(#name), std::borrow::Cow::Borrowed(#value))
+                }
+            });
+            fields.push(quote! {
+                headers: std::borrow::Cow::Borrowed(&[#(#headers),*])
+            });
+        }
+
+        #[cfg(feature = "encoder")]
+        {
+            // Blobs are read from a `static`, so can't be borrowed for `'static` in place, and
+            // non-empty variants are collected at runtime instead.
+            let variants = if file.encoded_variants.is_empty() {
+                quote!(std::borrow::Cow::Borrowed(&[]))
+            } else {
+                let variants = file
+                    .encoded_variants
+                    .iter()
+                    .map(|(key, data)| {
+                        let key = key.deref();
+                        let bytes = blobs.quote(data, None);
+                        quote! {
+                            (std::borrow::Cow::Borrowed(#key), #bytes)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                quote!(std::borrow::Cow::Owned(vec![#(#variants),*]))
+            };
+            fields.push(quote! {
+                encoded_variants: #variants
+            });
+        }
+
+        #[cfg(feature = "lazy")]
+        {
+            let omitted = file.lazy.is_omitted();
+            #[cfg(feature = "gzip")]
+            let gzip_level = match file.lazy.gzip_level() {
+                Some(level) => quote!(Some(#level)),
+                None => quote!(None),
+            };
+            #[cfg(not(feature = "gzip"))]
+            let gzip_level = quote!();
+            fields.push(quote! {
+                lazy: minicdn::Lazy::new(#omitted, #gzip_level)
+            });
+        }
+
+        // Generated files, like those from transforms, don't exist on disk. They are derived
+        // from files that do, which are tracked in their place.
+        let include_path_raw = Path::new(root_path).join(original);
+        let include_path = include_path_raw.exists().then(|| {
+            let include_path_canonical = include_path_raw.canonicalize().unwrap_or_else(|_| {
+                panic!("failed to canonicalize include path {:?}", include_path_raw)
+            });
+            include_path_canonical
+                .to_str()
+                .unwrap_or_else(|| {
+                    panic!(
+                        "failed to stringify include path: {:?}",
+                        include_path_canonical
+                    )
+                })
+                .to_owned()
+        });
+        let include_path = include_path.as_deref();
+        if let Some(include_path) = include_path {
+            track(include_path);
+            included.insert(include_path.to_owned());
+        }
+
+        // Must include the file instead of file.contents so a change triggers a recompilation.
+        // If the contents were altered, e.g. by rewriting references, or are shared with
+        // another file, include the original file anyway for the same reason.
+        let unaltered = include_path
+            .and_then(|path| std::fs::read(path).ok())
+            .as_deref()
+            == Some(&*file.contents);
+        let saved = blobs.saved;
+        let contents = blobs.quote(&file.contents, include_path.filter(|_| unaltered));
+        let contents = if let Some(include_path) = include_path {
+            quote! {
+                {
+                    const _: &[u8] = include_bytes!(#include_path);
+                    #contents
+                }
+            }
+        } else {
+            contents
+        };
+        blobs.files += 1;
+        if blobs.saved > saved {
+            blobs.deduplicated += 1;
+        }
+
+        files.insert(
+            original,
+            EmbeddedFile {
+                original,
+                paths: vec![path],
+                file: quote! {
+                    minicdn::MiniCdnFile{
+                        contents: #contents,
+                        #(#fields,)*
+                    }
+                },
+            },
+        );
+    }
+
+    // Config files, and the sources of renamed files, aren't included directly but still
+    // determine the output.
+    for source in minicdn_core::source_paths(root_path) {
+        let source = Path::new(&source)
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.to_str().map(str::to_owned));
+        if let Some(source) = source.filter(|source| !included.contains(source)) {
+            track(&source);
+            blobs.sources.push(source);
+        }
+    }
+
+    blobs.remove_unused();
+    files.into_values().collect()
+}
+
+/// Statements that record fingerprinted paths in `ret`.
+#[doc(hidden)]
+pub fn quote_manifest(mini_cdn: &EmbeddedMiniCdn) -> proc_macro2::TokenStream {
+    let mut manifest = mini_cdn.manifest().collect::<Vec<_>>();
+    manifest.sort_unstable();
+    let (originals, fingerprinted): (Vec<_>, Vec<_>) = manifest.into_iter().unzip();
+    quote! {
+        #(
+            ret.insert_fingerprinted(
+                std::borrow::Cow::Borrowed(#originals),
+                std::borrow::Cow::Borrowed(#fingerprinted),
+            );
+        )*
+    }
+}
+
+#[derive(Debug)]
+struct ByteStr<'a>(pub &'a [u8]);
+
+impl<'a> ToTokens for ByteStr<'a> {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.append(TokenTree::Literal(Literal::byte_string(self.0)));
+    }
+}
+
+/// Byte strings to be embedded, each only once (identified by the same hash as etags), in a
+/// `static` named [`Blobs::NAME`] that must be in scope wherever files are.
+#[doc(hidden)]
+pub struct Blobs {
+    /// Where blobs are written, shared only with output that would be identical.
+    dir: Option<PathBuf>,
+    /// Hashes of the blobs written to `dir` (or already there) by this output.
+    written: HashSet<String>,
+    /// Hash to index.
+    indices: HashMap<String, usize>,
+    /// Expressions of type `&'static [u8]`.
+    blobs: Vec<proc_macro2::TokenStream>,
+    /// Number of files that were embedded.
+    files: usize,
+    /// Number of files that shared another file's contents.
+    deduplicated: usize,
+    /// Bytes that would have been embedded more than once.
+    saved: usize,
+    /// Files that affect the output without being embedded, included so that a change triggers
+    /// a recompilation.
+    sources: Vec<String>,
+}
+
+impl Blobs {
+    pub const NAME: &'static str = "__MINICDN_BLOBS";
+
+    /// Blobs for output identified by `key` (e.g. the macro and its arguments), which are
+    /// written to their own directory in [`minicdn_core::default_cache_dir`], so that
+    /// [`embed_files`] can remove the ones that are no longer used.
+    pub fn new(key: &str) -> Self {
+        let dir = minicdn_core::default_cache_dir()
+            .map(|dir| dir.join("blobs").join(&sha256::digest(key)[..16]));
+        Self {
+            dir,
+            written: HashSet::new(),
+            indices: HashMap::new(),
+            blobs: Vec::new(),
+            files: 0,
+            deduplicated: 0,
+            saved: 0,
+            sources: Vec::new(),
+        }
+    }
+
+    /// An expression of type `Base64Bytes`, which reads from the [`Blobs::NAME`] `static`, so
+    /// can initialize other `static`s but can't be promoted to a `&'static` reference. The blob
+    /// is `include_bytes!` of `path`, if it is given and has the same contents, or otherwise of a
+    /// file written to this output's directory, since `include_bytes!` is much cheaper for rustc
+    /// than a large byte string literal. Falls back to a literal if there is nowhere to write.
+    fn quote(&mut self, data: &[u8], path: Option<&str>) -> proc_macro2::TokenStream {
+        if data.is_empty() {
+            return quote_bytes(data);
+        }
+        let hash = sha256::digest(data);
+        let index = if let Some(&index) = self.indices.get(&hash) {
+            self.saved += data.len();
+            index
+        } else {
+            let blob = path.map(str::to_owned).or_else(|| self.write(data, &hash));
+            self.blobs.push(if let Some(path) = blob {
+                quote!(include_bytes!(#path))
+            } else {
+                ByteStr(data).into_token_stream()
+            });
+            self.indices.insert(hash, self.blobs.len() - 1);
+            self.blobs.len() - 1
+        };
+        let name = Ident::new(Self::NAME, Span::call_site());
+        quote! {
+            minicdn::Base64Bytes::from_static(#name[#index])
+        }
+    }
+
+    #[allow(unused)]
+    fn quote_option(
+        &mut self,
+        data: &Option<minicdn_core::Base64Bytes>,
+    ) -> proc_macro2::TokenStream {
+        if let Some(data) = data {
+            let bytes = self.quote(data, None);
+            quote! {
+                Some(#bytes)
+            }
+        } else {
+            quote! {
+                None
+            }
+        }
+    }
+
+    /// Writes `data` to a file named after its `hash`, returning the path.
+    fn write(&mut self, data: &[u8], hash: &str) -> Option<String> {
+        let path = self.dir.as_ref()?.join(hash);
+        // Files are named after their contents, so existing files are already correct.
+        if !path.is_file() {
+            minicdn_core::write_atomic(&path, data).ok()?;
+        }
+        self.written.insert(hash.to_owned());
+        path.canonicalize().ok()?.to_str().map(str::to_owned)
+    }
+
+    /// Removes blobs written by previous versions of this output that it no longer uses. Errors
+    /// are ignored, like other errors using the cache.
+    fn remove_unused(&self) {
+        let Some(Ok(entries)) = self.dir.as_ref().map(std::fs::read_dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let unused = name.to_str().is_some_and(|name| {
+                // Leaves temporary files, which may be being written by a concurrent build.
+                !name.ends_with(".tmp") && !self.written.contains(name)
+            });
+            if unused {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    /// Describes how much deduplication saved, if anything.
+    pub fn report(&self, root_path: &str) -> Option<String> {
+        (self.saved > 0).then(|| {
+            format!(
+                "minicdn: {} of {} files in {:?} were duplicates, saving {} bytes",
+                self.deduplicated, self.files, root_path, self.saved
+            )
+        })
+    }
+}
+
+impl ToTokens for Blobs {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        let name = Ident::new(Self::NAME, Span::call_site());
+        let blobs = &self.blobs;
+        let count = blobs.len();
+        let sources = &self.sources;
+        tokens.extend(quote! {
+            static #name: [&[u8]; #count] = [#(#blobs),*];
+            #(const _: &[u8] = include_bytes!(#sources);)*
+        });
+    }
+}
+
+fn quote_bytes(data: &[u8]) -> proc_macro2::TokenStream {
+    let bytes = ByteStr(data);
+    quote! {
+        minicdn::Base64Bytes::from_static(#bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The test fixture shared with `minicdn`.
+    fn fixture() -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/tree");
+        path.canonicalize().unwrap().to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn dedup() {
+        let mut blobs = Blobs::new("dedup");
+        let first = blobs.quote(b"same", None).to_string();
+        let second = blobs.quote(b"same", None).to_string();
+        blobs.quote(b"different", None);
+        assert_eq!(first, second);
+        assert_eq!(blobs.blobs.len(), 2);
+        assert_eq!(blobs.saved, 4);
+
+        // The images all have the same contents.
+        let root_path = fixture();
+        let mini_cdn = EmbeddedMiniCdn::compress(&root_path, &CompressOptions::default()).mini_cdn;
+        let mut blobs = Blobs::new("dedup");
+        embed_files(&root_path, &mini_cdn, &mut blobs, &mut |_| {});
+        let report = blobs.report(&root_path).unwrap();
+        assert!(report.contains("3 of "), "{}", report);
+    }
+
+    #[test]
+    fn remove_unused() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs = |data: &[u8]| {
+            let mut blobs = Blobs::new("remove_unused");
+            blobs.dir = Some(dir.path().to_owned());
+            blobs.quote(data, None);
+            blobs.remove_unused();
+        };
+        let count = || std::fs::read_dir(dir.path()).unwrap().count();
+        blobs(b"old");
+        assert_eq!(count(), 1);
+        blobs(b"new");
+        assert_eq!(count(), 1);
+        assert!(dir.path().join(sha256::digest(b"new".as_slice())).is_file());
+    }
+
+    #[test]
+    fn reproducible() {
+        // Each compression has its own map, iterated in a different order.
+        let root_path = fixture();
+        let generate = || {
+            let mini_cdn =
+                EmbeddedMiniCdn::compress(&root_path, &CompressOptions::default()).mini_cdn;
+            quote_embedded_mini_cdn(&root_path, &mini_cdn, "test", &mut |_| {}, &mut |_| {})
+                .to_string()
+        };
+        assert_eq!(generate(), generate());
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+/// Brotli (quality, window size) pairs to try, most promising first.
+#[cfg(feature = "brotli")]
+pub(crate) fn brotli_candidates(configured: (u8, u8)) -> impl Iterator<Item = (u8, u8)> {
+    std::iter::once(configured).chain(
+        [11, 10, 9]
+            .into_iter()
+            .flat_map(|quality| [24, 22, 20, 18].map(|window| (quality, window)))
+            .filter(move |&candidate| candidate != configured),
+    )
+}
+
+/// GZIP levels to try, most promising first.
+#[cfg(feature = "gzip")]
+pub(crate) fn gzip_candidates(configured: u8) -> impl Iterator<Item = u8> {
+    std::iter::once(configured).chain((6..=9).rev().filter(move |&level| level != configured))
+}
+
+/// Compresses with each of the first `limit` candidates, in order, keeping the smallest output
+/// along with the candidate that produced it. Always tries the first candidate, but doesn't
+/// start another once `budget` has passed, if given. A candidate that is already running is
+/// finished, so the budget is best-effort, and each search (e.g. Brotli and GZIP of one file)
+/// has its own.
+pub(crate) fn search<P: Copy>(
+    candidates: impl IntoIterator<Item = P>,
+    limit: usize,
+    budget: Option<Duration>,
+    compress: impl Fn(P) -> Vec<u8>,
+) -> (P, Vec<u8>) {
+    let start = Instant::now();
+    let mut candidates = candidates.into_iter().take(limit.max(1));
+    let first = candidates.next().expect("no candidates");
+    let mut best = (first, compress(first));
+    for candidate in candidates {
+        if budget.is_some_and(|budget| start.elapsed() >= budget) {
+            break;
+        }
+        let output = compress(candidate);
+        if output.len() < best.1.len() {
+            best = (candidate, output);
+        }
+    }
+    best
+}
@@ -1,5 +1,7 @@
 #[cfg(feature = "use_serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(not(feature = "bytes"))]
+use std::borrow::Cow;
 use std::fmt;
 use std::ops::Deref;
 
@@ -8,16 +10,16 @@ use std::ops::Deref;
 #[repr(transparent)]
 pub struct Base64Bytes(
     #[cfg(feature = "bytes")] bytes::Bytes,
-    #[cfg(not(feature = "bytes"))] Vec<u8>,
+    #[cfg(not(feature = "bytes"))] Cow<'static, [u8]>,
 );
 
 impl Base64Bytes {
-    pub fn from_static(bytes: &'static [u8]) -> Self {
+    pub const fn from_static(bytes: &'static [u8]) -> Self {
         #[cfg(feature = "bytes")]
         return Self(bytes::Bytes::from_static(bytes));
 
         #[cfg(not(feature = "bytes"))]
-        Self(bytes.to_owned())
+        Self(Cow::Borrowed(bytes))
     }
 }
 
@@ -40,7 +42,7 @@ impl From<Vec<u8>> for Base64Bytes {
         #[cfg(feature = "bytes")]
         return Self(bytes::Bytes::from(val));
         #[cfg(not(feature = "bytes"))]
-        Self(val)
+        Self(Cow::Owned(val))
     }
 }
 
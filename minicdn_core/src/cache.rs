@@ -0,0 +1,143 @@
+#[cfg(feature = "cache")]
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "cache")]
+use std::sync::{Mutex, PoisonError};
+
+/// Changes whenever compressed output might change for the same input and parameters, which
+/// invalidates all cached output. Encoder crates are identified by their semver-compatible
+/// version in `Cargo.toml`.
+#[cfg(feature = "cache")]
+const VERSION: &str = concat!(
+    "minicdn_core ",
+    env!("CARGO_PKG_VERSION"),
+    ", brotli 3, flate2 1, webp 0.3, zopfli 0.8"
+);
+
+/// Where `minicdn_build` and the `include_mini_cdn!` family of macros cache compressed output
+/// by default, if anywhere. They also write compressed files here, in order to `include_bytes!`
+/// them.
+///
+/// In order, this is the `MINICDN_CACHE_DIR` environment variable, `minicdn` inside `OUT_DIR`
+/// (when called from a build script) or `CARGO_TARGET_DIR`, or `minicdn` inside the nearest
+/// Cargo `target` directory above `CARGO_MANIFEST_DIR` (or the current directory).
+pub fn default_cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("MINICDN_CACHE_DIR") {
+        return Some(dir.into());
+    }
+    for var in ["OUT_DIR", "CARGO_TARGET_DIR"] {
+        if let Some(dir) = std::env::var_os(var) {
+            return Some(Path::new(&dir).join("minicdn"));
+        }
+    }
+    let start = std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|| std::env::current_dir().ok())?;
+    start
+        .ancestors()
+        .map(|dir| dir.join("target"))
+        // Cargo marks its target directory with this file.
+        .find(|target| target.join("CACHEDIR.TAG").is_file())
+        .map(|target| target.join("minicdn"))
+}
+
+/// Returns the output of `compress` from a previous build with the same `encoder`, `parameters`
+/// and `contents`, or runs it and saves the output for next time.
+///
+/// Errors reading or writing the cache are ignored, falling back to compressing.
+#[cfg(feature = "cache")]
+pub(crate) fn get_or_insert_with(
+    dir: &Path,
+    encoder: &str,
+    parameters: &str,
+    contents: &[u8],
+    compress: impl FnOnce() -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let key = sha256::digest(
+        [
+            VERSION.as_bytes(),
+            b"\0",
+            encoder.as_bytes(),
+            b"\0",
+            parameters.as_bytes(),
+            b"\0",
+            sha256::digest(contents).as_bytes(),
+        ]
+        .concat(),
+    );
+    let dir = dir.join(version_dir());
+    remove_stale(dir.parent().unwrap());
+    let path = dir.join(&key[..2]).join(&key);
+
+    // The first byte records whether compression was worthwhile.
+    if let Ok(cached) = std::fs::read(&path) {
+        match cached.split_first() {
+            Some((0, _)) => return None,
+            Some((1, compressed)) => return Some(compressed.to_vec()),
+            _ => {}
+        }
+    }
+
+    let compressed = compress();
+    let mut cached = Vec::with_capacity(compressed.as_ref().map(Vec::len).unwrap_or(0) + 1);
+    cached.push(compressed.is_some() as u8);
+    cached.extend_from_slice(compressed.as_deref().unwrap_or_default());
+    let _ = write_atomic(&path, &cached);
+    compressed
+}
+
+/// The subdirectory that output for this [`VERSION`] is cached in. Output from other versions
+/// can never be read again, so it is removed by [`remove_stale`].
+#[cfg(feature = "cache")]
+fn version_dir() -> String {
+    format!("v{}", &sha256::digest(VERSION)[..16])
+}
+
+/// Removes output cached by other versions from `dir`, once per process. Only subdirectories
+/// named like [`version_dir`] are removed, in case `dir` is shared with something else.
+#[cfg(feature = "cache")]
+fn remove_stale(dir: &Path) {
+    static CLEANED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+    let mut cleaned = CLEANED.lock().unwrap_or_else(PoisonError::into_inner);
+    if !cleaned.insert(dir.to_owned()) {
+        return;
+    }
+    let current = version_dir();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        let stale = name != current
+            && name.len() == current.len()
+            && name.strip_prefix('v').is_some_and(|hash| {
+                hash.bytes()
+                    .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+            });
+        if stale {
+            // Errors are ignored, like other errors using the cache.
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Writes to a temporary file first, so concurrent builds never see partial output.
+#[doc(hidden)]
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let temporary = path.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
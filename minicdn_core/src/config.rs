@@ -0,0 +1,513 @@
+#[cfg(any(feature = "config", feature = "minify_css"))]
+use std::collections::BTreeMap;
+
+#[cfg(any(
+    feature = "webp",
+    feature = "minify_html",
+    feature = "minify_css",
+    feature = "minify_js",
+    feature = "minify_json",
+    feature = "minify_svg",
+    feature = "markdown"
+))]
+fn default_true() -> bool {
+    true
+}
+
+#[cfg(feature = "brotli")]
+fn default_brotli_level() -> u8 {
+    9
+}
+
+#[cfg(feature = "brotli")]
+fn default_brotli_buffer_size() -> usize {
+    4096
+}
+
+#[cfg(feature = "brotli")]
+fn default_brotli_large_window_size() -> u8 {
+    20
+}
+
+#[cfg(feature = "gzip")]
+fn default_gzip_level() -> u8 {
+    8
+}
+
+#[cfg(feature = "zopfli")]
+fn default_zopfli_iterations() -> u64 {
+    15
+}
+
+/// How to produce GZIP contents.
+#[cfg(feature = "zopfli")]
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "config", derive(serde::Deserialize))]
+#[cfg_attr(feature = "config", serde(rename_all = "lowercase"))]
+pub(crate) enum GzipAlgorithm {
+    /// Fast, using `gzip_level`.
+    #[default]
+    Flate2,
+    /// Slow, but optimal, using `zopfli_iterations`.
+    Zopfli,
+}
+
+#[cfg(any(feature = "brotli", feature = "gzip"))]
+fn default_auto_candidates() -> usize {
+    4
+}
+
+#[cfg(feature = "webp")]
+fn default_webp_quality() -> Option<f32> {
+    Some(90.0)
+}
+
+#[cfg(feature = "fingerprint")]
+fn default_fingerprint() -> bool {
+    true
+}
+
+/// Per-file options, read from a file with the [`crate::CONFIG_SUFFIX`] suffix.
+#[derive(Clone)]
+#[cfg_attr(feature = "config", derive(serde::Deserialize))]
+pub(crate) struct Config {
+    /// Overrides [`crate::CompressOptions::compression_threshold`].
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "webp",
+        feature = "encoder"
+    ))]
+    #[cfg_attr(
+        feature = "config",
+        serde(default, deserialize_with = "deserialize_compression_threshold")
+    )]
+    pub compression_threshold: Option<f32>,
+    /// Whether to compress with Brotli at all. If unset, only files that aren't
+    /// [`RootConfig::incompressible`] are compressed.
+    #[cfg(feature = "brotli")]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub brotli: Option<bool>,
+    #[cfg(feature = "brotli")]
+    #[cfg_attr(feature = "config", serde(default = "default_brotli_level"))]
+    pub brotli_level: u8,
+    #[cfg(feature = "brotli")]
+    #[cfg_attr(feature = "config", serde(default = "default_brotli_buffer_size"))]
+    pub brotli_buffer_size: usize,
+    #[cfg(feature = "brotli")]
+    #[cfg_attr(
+        feature = "config",
+        serde(default = "default_brotli_large_window_size")
+    )]
+    pub brotli_large_window_size: u8,
+    /// Whether to compress with GZIP at all. If unset, only files that aren't
+    /// [`RootConfig::incompressible`] are compressed.
+    #[cfg(feature = "gzip")]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub gzip: Option<bool>,
+    #[cfg(feature = "gzip")]
+    #[cfg_attr(feature = "config", serde(default = "default_gzip_level"))]
+    pub gzip_level: u8,
+    #[cfg(feature = "zopfli")]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub gzip_algorithm: GzipAlgorithm,
+    #[cfg(feature = "zopfli")]
+    #[cfg_attr(feature = "config", serde(default = "default_zopfli_iterations"))]
+    pub zopfli_iterations: u64,
+    /// Search for the Brotli quality and window size, and GZIP level, that produce the smallest
+    /// output, starting with the configured ones.
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub auto: bool,
+    /// How many parameters `auto` may try for each of Brotli and GZIP, including the configured
+    /// ones.
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    #[cfg_attr(feature = "config", serde(default = "default_auto_candidates"))]
+    pub auto_candidates: usize,
+    /// Stops `auto` trying further parameters (after the configured ones) once this many
+    /// milliseconds have passed, separately for Brotli and GZIP. It is only checked between
+    /// attempts, so may be exceeded by one attempt. The choice then depends on how fast the
+    /// machine is, and is cached like any other, so later builds reuse it.
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub auto_budget_ms: Option<u64>,
+    /// Whether to convert images to WebP at all.
+    #[cfg(feature = "webp")]
+    #[cfg_attr(feature = "config", serde(default = "default_true"))]
+    pub webp: bool,
+    #[cfg(feature = "webp")]
+    #[cfg_attr(
+        feature = "config",
+        serde(
+            default = "default_webp_quality",
+            deserialize_with = "deserialize_webp_quality"
+        )
+    )]
+    pub webp_quality: Option<f32>,
+    /// Whether to minify, if the MIME type supports it.
+    #[cfg(any(
+        feature = "minify_html",
+        feature = "minify_css",
+        feature = "minify_js",
+        feature = "minify_json",
+        feature = "minify_svg"
+    ))]
+    #[cfg_attr(feature = "config", serde(default = "default_true"))]
+    pub minify: bool,
+    /// Whether to keep comments like `/*! ... */` or those containing `@license` when
+    /// minifying JS.
+    #[cfg(feature = "minify_js")]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub keep_license_comments: bool,
+    /// Whether to generate a source map, served at the original path plus `.map`, when
+    /// minifying JS.
+    #[cfg(feature = "minify_js")]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub source_map: bool,
+    /// Whether to fingerprint, if [`crate::CompressOptions::fingerprint`] is enabled.
+    #[cfg(feature = "fingerprint")]
+    #[cfg_attr(feature = "config", serde(default = "default_fingerprint"))]
+    pub fingerprint: bool,
+    /// Extra response headers, sorted by name.
+    #[cfg(feature = "config")]
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[cfg(feature = "config")]
+impl Config {
+    /// Parses a config file.
+    pub fn parse(contents: &[u8]) -> Result<Self, toml::de::Error> {
+        toml::from_slice(contents)
+    }
+}
+
+/// Options for all files, read from a file named [`crate::CONFIG_SUFFIX`] in the root directory.
+#[cfg_attr(feature = "config", derive(serde::Deserialize))]
+pub(crate) struct RootConfig {
+    /// MIME types (or `type/*` patterns) that Brotli and GZIP are not attempted on, because
+    /// they are already compressed.
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    #[cfg_attr(feature = "config", serde(default = "default_incompressible"))]
+    pub incompressible: Vec<String>,
+    /// Browsers to lower modern CSS syntax for.
+    #[cfg(feature = "minify_css")]
+    #[cfg_attr(
+        feature = "config",
+        serde(default, deserialize_with = "deserialize_css_targets")
+    )]
+    pub css_targets: CssTargets,
+    /// Template to render Markdown files into, relative to the root directory. It isn't served
+    /// itself.
+    #[cfg(feature = "markdown")]
+    #[cfg_attr(feature = "config", serde(default))]
+    pub markdown_layout: Option<String>,
+    /// Whether to serve Markdown files as they are, as well as rendered.
+    #[cfg(feature = "markdown")]
+    #[cfg_attr(feature = "config", serde(default = "default_true"))]
+    pub markdown_keep_original: bool,
+}
+
+/// Browsers to lower modern CSS syntax for, see [`RootConfig::css_targets`].
+#[cfg(feature = "minify_css")]
+#[derive(Clone, Debug)]
+pub(crate) enum CssTargets {
+    /// Minimum versions, e.g. `safari = "14.1"`.
+    Versions(BTreeMap<String, String>),
+    /// Browserslist queries, e.g. `"> 0.5%, last 2 versions"`, resolved with the browser usage
+    /// data bundled with `browserslist-rs`.
+    Query(Vec<String>),
+}
+
+#[cfg(feature = "minify_css")]
+impl Default for CssTargets {
+    fn default() -> Self {
+        Self::Versions(BTreeMap::new())
+    }
+}
+
+#[cfg(any(feature = "brotli", feature = "gzip"))]
+fn default_incompressible() -> Vec<String> {
+    [
+        "application/gzip",
+        "application/x-7z-compressed",
+        "application/x-bzip2",
+        "application/x-rar-compressed",
+        "application/x-xz",
+        "application/zip",
+        "application/zstd",
+        "audio/*",
+        "font/woff",
+        "font/woff2",
+        "image/avif",
+        "image/gif",
+        "image/jpeg",
+        "image/png",
+        "image/webp",
+        "video/*",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+impl RootConfig {
+    /// Parses a root config file.
+    #[cfg(feature = "config")]
+    pub fn parse(contents: &[u8]) -> Result<Self, toml::de::Error> {
+        toml::from_slice(contents)
+    }
+
+    /// Whether files of this MIME type are already compressed.
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    pub fn is_incompressible(&self, mime: &str) -> bool {
+        self.incompressible
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some(top_level) => mime.split('/').next() == Some(top_level),
+                None => pattern == mime,
+            })
+    }
+}
+
+impl Default for RootConfig {
+    fn default() -> Self {
+        Self {
+            #[cfg(any(feature = "brotli", feature = "gzip"))]
+            incompressible: default_incompressible(),
+            #[cfg(feature = "minify_css")]
+            css_targets: CssTargets::default(),
+            #[cfg(feature = "markdown")]
+            markdown_layout: None,
+            #[cfg(feature = "markdown")]
+            markdown_keep_original: default_true(),
+        }
+    }
+}
+
+/// The name of the config file (without [`crate::CONFIG_SUFFIX`]) that applies to a file.
+#[cfg(feature = "config")]
+pub(crate) fn config_name(relative_path: &str) -> &str {
+    if let Some((before, _)) = relative_path.split_once('.') {
+        before
+    } else {
+        relative_path
+    }
+}
+
+#[cfg(all(feature = "webp", feature = "config"))]
+fn deserialize_webp_quality<'de, D: serde::de::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f32>, D::Error> {
+    struct QualityOrLossless;
+
+    impl<'de> serde::de::Visitor<'de> for QualityOrLossless {
+        type Value = Option<f32>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("numerical quality or string \"lossless\"")
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            if value == "lossless" {
+                Ok(None)
+            } else {
+                Err(E::invalid_value(
+                    serde::de::Unexpected::Str(value),
+                    &"the string \"lossless\"",
+                ))
+            }
+        }
+
+        fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            if (0f64..=100f64).contains(&v) {
+                Ok(Some(v as f32))
+            } else {
+                Err(E::invalid_value(
+                    serde::de::Unexpected::Float(v),
+                    &"a quality between 0 and 100",
+                ))
+            }
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            self.visit_f64(v as f64)
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            self.visit_f64(v as f64)
+        }
+    }
+
+    deserializer.deserialize_any(QualityOrLossless)
+}
+
+#[cfg(all(
+    any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "webp",
+        feature = "encoder"
+    ),
+    feature = "config"
+))]
+fn deserialize_compression_threshold<'de, D: serde::de::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<f32>, D::Error> {
+    use serde::de::Error;
+
+    let threshold: f32 = serde::Deserialize::deserialize(deserializer)?;
+    if (0.0..=1.0).contains(&threshold) {
+        Ok(Some(threshold))
+    } else {
+        Err(D::Error::custom(format!(
+            "compression_threshold must be between 0 and 1, got {}",
+            threshold
+        )))
+    }
+}
+
+/// Checks that headers are valid, so they can't break responses (e.g. by injecting others).
+#[cfg(feature = "config")]
+fn deserialize_headers<'de, D: serde::de::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    use serde::de::Error;
+
+    let headers: BTreeMap<String, String> = serde::Deserialize::deserialize(deserializer)?;
+    for (name, value) in &headers {
+        // A token, as defined by RFC 9110.
+        let is_token = !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !is_token {
+            return Err(D::Error::custom(format!("invalid header name {:?}", name)));
+        }
+        if value.contains(['\r', '\n', '\0']) {
+            return Err(D::Error::custom(format!(
+                "invalid value for header {:?}: {:?}",
+                name, value
+            )));
+        }
+    }
+    Ok(headers)
+}
+
+#[cfg(all(feature = "minify_css", feature = "config"))]
+fn deserialize_css_targets<'de, D: serde::de::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<CssTargets, D::Error> {
+    struct Targets;
+
+    impl Targets {
+        /// Checks that the queries resolve, so mistakes are reported with the config file.
+        fn query<E: serde::de::Error>(queries: Vec<String>) -> Result<CssTargets, E> {
+            lightningcss::targets::Browsers::from_browserslist(&queries).map_err(|e| {
+                E::custom(format!("invalid browserslist query {:?}: {}", queries, e))
+            })?;
+            Ok(CssTargets::Query(queries))
+        }
+    }
+
+    impl<'de> serde::de::Visitor<'de> for Targets {
+        type Value = CssTargets;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("browserslist queries or a table of minimum browser versions")
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            Self::query(vec![value.to_owned()])
+        }
+
+        fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::SeqAccess<'de>,
+        {
+            Self::query(serde::Deserialize::deserialize(
+                serde::de::value::SeqAccessDeserializer::new(seq),
+            )?)
+        }
+
+        fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+        where
+            A: serde::de::MapAccess<'de>,
+        {
+            serde::Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                .map(CssTargets::Versions)
+        }
+    }
+
+    deserializer.deserialize_any(Targets)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            #[cfg(any(
+                feature = "brotli",
+                feature = "gzip",
+                feature = "webp",
+                feature = "encoder"
+            ))]
+            compression_threshold: None,
+            #[cfg(feature = "brotli")]
+            brotli: None,
+            #[cfg(feature = "brotli")]
+            brotli_level: default_brotli_level(),
+            #[cfg(feature = "brotli")]
+            brotli_buffer_size: default_brotli_buffer_size(),
+            #[cfg(feature = "brotli")]
+            brotli_large_window_size: default_brotli_large_window_size(),
+            #[cfg(feature = "gzip")]
+            gzip: None,
+            #[cfg(feature = "gzip")]
+            gzip_level: default_gzip_level(),
+            #[cfg(feature = "zopfli")]
+            gzip_algorithm: GzipAlgorithm::default(),
+            #[cfg(feature = "zopfli")]
+            zopfli_iterations: default_zopfli_iterations(),
+            #[cfg(any(feature = "brotli", feature = "gzip"))]
+            auto: false,
+            #[cfg(any(feature = "brotli", feature = "gzip"))]
+            auto_candidates: default_auto_candidates(),
+            #[cfg(any(feature = "brotli", feature = "gzip"))]
+            auto_budget_ms: None,
+            #[cfg(feature = "webp")]
+            webp: default_true(),
+            #[cfg(feature = "webp")]
+            webp_quality: default_webp_quality(),
+            #[cfg(any(
+                feature = "minify_html",
+                feature = "minify_css",
+                feature = "minify_js",
+                feature = "minify_json",
+                feature = "minify_svg"
+            ))]
+            minify: default_true(),
+            #[cfg(feature = "minify_js")]
+            keep_license_comments: false,
+            #[cfg(feature = "minify_js")]
+            source_map: false,
+            #[cfg(feature = "fingerprint")]
+            fingerprint: default_fingerprint(),
+            #[cfg(feature = "config")]
+            headers: BTreeMap::new(),
+        }
+    }
+}
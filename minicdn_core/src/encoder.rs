@@ -0,0 +1,32 @@
+/// An additional form of files, produced by
+/// [`crate::EmbeddedMiniCdn::new_compressed_with_options`] (see
+/// [`crate::CompressOptions::encoders`]) and stored in
+/// [`crate::MiniCdnFile::encoded_variants`].
+///
+/// ```
+/// use minicdn_core::Encoder;
+///
+/// /// Encodes text with the (hypothetical) `x-shout` content-coding.
+/// #[derive(Debug)]
+/// struct Shout;
+///
+/// impl Encoder for Shout {
+///     fn key(&self) -> &str {
+///         "x-shout"
+///     }
+///
+///     fn encode(&self, mime: &str, contents: &[u8]) -> Option<Vec<u8>> {
+///         mime.starts_with("text/").then(|| contents.to_ascii_uppercase())
+///     }
+/// }
+/// ```
+pub trait Encoder: std::fmt::Debug + Send + Sync {
+    /// The content-coding (e.g. `zstd`) of the output or, if it is a different representation
+    /// altogether, its media type (e.g. `image/avif`).
+    fn key(&self) -> &str;
+
+    /// Encodes a file, or returns `None` if this encoder doesn't apply to it. Like the built-in
+    /// forms, the output is discarded if it isn't small enough (see
+    /// [`crate::CompressOptions::compression_threshold`]).
+    fn encode(&self, mime: &str, contents: &[u8]) -> Option<Vec<u8>>;
+}
@@ -0,0 +1,59 @@
+use crate::Base64Bytes;
+use std::sync::OnceLock;
+
+/// Tracks which forms of a file were left out to save space (see
+/// [`crate::CompressOptions::lazy`]), and caches them once reconstructed.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lazy {
+    /// Whether `contents` was left out, to be decompressed from `contents_brotli`.
+    omitted: bool,
+    /// If `contents_gzip` was left out, the level to recompress it with.
+    #[cfg(feature = "gzip")]
+    gzip_level: Option<u8>,
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    contents: OnceLock<Base64Bytes>,
+    #[cfg(feature = "gzip")]
+    #[cfg_attr(feature = "use_serde", serde(skip))]
+    contents_gzip: OnceLock<Base64Bytes>,
+}
+
+impl Lazy {
+    #[doc(hidden)]
+    pub const fn new(omitted: bool, #[cfg(feature = "gzip")] gzip_level: Option<u8>) -> Self {
+        Self {
+            omitted,
+            #[cfg(feature = "gzip")]
+            gzip_level,
+            contents: OnceLock::new(),
+            #[cfg(feature = "gzip")]
+            contents_gzip: OnceLock::new(),
+        }
+    }
+
+    /// Whether `contents` was left out.
+    pub fn is_omitted(&self) -> bool {
+        self.omitted
+    }
+
+    /// If `contents_gzip` was left out, the level to recompress it with.
+    #[cfg(feature = "gzip")]
+    pub fn gzip_level(&self) -> Option<u8> {
+        self.gzip_level
+    }
+
+    pub(crate) fn contents(&self, brotli: &[u8]) -> &Base64Bytes {
+        self.contents.get_or_init(|| {
+            let mut contents = Vec::new();
+            brotli::BrotliDecompress(&mut &*brotli, &mut contents)
+                .expect("failed to decompress embedded file");
+            contents.into()
+        })
+    }
+
+    #[cfg(feature = "gzip")]
+    pub(crate) fn contents_gzip(&self, contents: &[u8], level: u8) -> &Base64Bytes {
+        self.contents_gzip
+            .get_or_init(|| crate::gzip(contents, level).into())
+    }
+}
@@ -1,6 +1,52 @@
+#[cfg(all(feature = "walkdir", any(feature = "brotli", feature = "gzip")))]
+mod auto;
 mod bytes;
+#[cfg(feature = "walkdir")]
+mod cache;
+#[cfg(feature = "walkdir")]
+mod config;
+#[cfg(feature = "encoder")]
+mod encoder;
+#[cfg(feature = "lazy")]
+mod lazy;
+#[cfg(feature = "markdown")]
+mod markdown;
+#[cfg(all(
+    any(
+        feature = "minify_html",
+        feature = "minify_css",
+        feature = "minify_js",
+        feature = "minify_json",
+        feature = "minify_svg"
+    ),
+    feature = "walkdir"
+))]
+mod minify;
+#[cfg(all(feature = "rewrite", feature = "walkdir"))]
+mod rewrite;
+#[cfg(feature = "sass")]
+mod sass;
+#[cfg(feature = "transform")]
+mod transform;
 
 pub use crate::bytes::Base64Bytes;
+#[cfg(feature = "walkdir")]
+pub use crate::cache::{default_cache_dir, write_atomic};
+#[cfg(feature = "walkdir")]
+use crate::config::{Config, RootConfig};
+#[cfg(feature = "encoder")]
+pub use crate::encoder::Encoder;
+#[cfg(feature = "lazy")]
+pub use crate::lazy::Lazy;
+#[cfg(all(feature = "transform", feature = "config"))]
+pub use crate::transform::TransformConfig;
+#[cfg(feature = "transform")]
+pub use crate::transform::{CommandTransform, Transform, TransformInput, TransformOutput};
+#[cfg(feature = "bytestring")]
+pub use bytestring::ByteString;
+#[cfg(feature = "phf")]
+#[doc(hidden)]
+pub use phf;
 use std::borrow::Cow;
 use std::collections::HashMap;
 
@@ -15,6 +61,9 @@ pub enum MiniCdn {
     Embedded(EmbeddedMiniCdn),
     #[cfg(feature = "walkdir")]
     Filesystem(FilesystemMiniCdn),
+    #[cfg(feature = "phf")]
+    #[cfg_attr(feature = "use_serde", serde(skip_deserializing))]
+    Static(&'static StaticMiniCdn),
 }
 
 /// A collection of files loaded from the compiled binary.
@@ -22,6 +71,19 @@ pub enum MiniCdn {
 #[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EmbeddedMiniCdn {
     files: HashMap<Cow<'static, str>, MiniCdnFile>,
+    /// Maps original paths to fingerprinted paths.
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    manifest: HashMap<Cow<'static, str>, Cow<'static, str>>,
+}
+
+/// A collection of files loaded from the compiled binary, as a `static` perfect hash map that
+/// requires no initialization.
+#[derive(Debug)]
+#[cfg(feature = "phf")]
+pub struct StaticMiniCdn {
+    files: phf::Map<&'static str, &'static MiniCdnFile>,
+    /// Maps original paths to fingerprinted paths.
+    manifest: phf::Map<&'static str, &'static str>,
 }
 
 /// A collection of files loaded from the filesystem at runtime.
@@ -32,6 +94,67 @@ pub struct FilesystemMiniCdn {
     root_path: Cow<'static, str>,
 }
 
+/// Options for [`EmbeddedMiniCdn::new_compressed_with_options`].
+#[derive(Clone, Debug, Default)]
+pub struct CompressOptions {
+    /// Serve files at content-hashed paths, e.g. `app.3f2a9c1e.js` instead of `app.js`. Use
+    /// [`EmbeddedMiniCdn::fingerprinted`] to look up the new paths. Individual files may opt out
+    /// with `fingerprint = false` in their config file.
+    #[cfg(feature = "fingerprint")]
+    pub fingerprint: bool,
+    /// When fingerprinting, continue to serve files at their original paths as well.
+    #[cfg(feature = "fingerprint")]
+    pub fingerprint_keep_original: bool,
+    /// When fingerprinting, rewrite references to fingerprinted files inside HTML and CSS files.
+    /// Files whose references lead back to themselves aren't fingerprinted.
+    #[cfg(feature = "rewrite")]
+    pub fingerprint_rewrite: bool,
+    /// Reuse compressed output from previous builds, saved in this directory. Nothing is cached
+    /// unless this is set, except by `minicdn_build` and the `include_mini_cdn!` family of macros,
+    /// which default to [`default_cache_dir`] (see [`CompressOptions::build_time`]).
+    #[cfg(feature = "cache")]
+    pub cache_dir: Option<std::path::PathBuf>,
+    /// Only store the Brotli form of files that have one, leaving out `contents` and
+    /// `contents_gzip`. These are reconstructed on first access via [`MiniCdnFile::contents`] and
+    /// [`MiniCdnFile::contents_gzip`], trading some CPU for a smaller binary. GZIP produced by
+    /// zopfli or `auto` is kept, since recompressing wouldn't reproduce it.
+    #[cfg(feature = "lazy")]
+    pub lazy: bool,
+    /// Only keep compressed forms smaller than this fraction of the original size, between 0
+    /// and 1. Defaults to 0.9. Individual files may override this with `compression_threshold`
+    /// in their config file.
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "webp",
+        feature = "encoder"
+    ))]
+    pub compression_threshold: Option<f32>,
+    /// Custom forms of each file, stored in [`MiniCdnFile::encoded_variants`] and iterated by
+    /// [`MiniCdnFile::variants`] along with the built-in ones. The macros can't run custom code, so use `minicdn_build` from a build script (or
+    /// compress at runtime) instead.
+    #[cfg(feature = "encoder")]
+    pub encoders: Vec<std::sync::Arc<dyn Encoder>>,
+    /// Custom processing, applied to each file in order, before built-in minification. The
+    /// macros only support [`CommandTransform`]s, registered with their `transform` option.
+    #[cfg(feature = "transform")]
+    pub transforms: Vec<std::sync::Arc<dyn Transform>>,
+}
+
+impl CompressOptions {
+    /// These options with the defaults used at compile time, by `minicdn_build` and the macros.
+    /// Unlike at runtime, caching is on by default, in [`default_cache_dir`].
+    pub fn build_time(&self) -> Self {
+        #[allow(unused_mut)]
+        let mut options = self.clone();
+        #[cfg(feature = "cache")]
+        if options.cache_dir.is_none() {
+            options.cache_dir = default_cache_dir();
+        }
+        options
+    }
+}
+
 impl Default for MiniCdn {
     fn default() -> Self {
         Self::Embedded(EmbeddedMiniCdn::default())
@@ -50,258 +173,233 @@ pub struct MiniCdnFile {
     /// MIME type.
     #[cfg(feature = "mime")]
     pub mime: bytestring::ByteString,
-    /// Raw bytes of file.
+    /// Raw bytes of file. Empty if they were left out with [`CompressOptions::lazy`], so prefer
+    /// [`MiniCdnFile::contents`], which works either way.
     pub contents: Base64Bytes,
     /// Contents compressed as Brotli.
     #[cfg(feature = "brotli")]
     pub contents_brotli: Option<Base64Bytes>,
-    /// Contents compressed as GZIP.
+    /// Contents compressed as GZIP. `None` if they were left out with [`CompressOptions::lazy`],
+    /// so prefer [`MiniCdnFile::contents_gzip`], which works either way.
     #[cfg(feature = "gzip")]
     pub contents_gzip: Option<Base64Bytes>,
     /// Contents compressed as WebP (only applies to images).
     #[cfg(feature = "webp")]
     pub contents_webp: Option<Base64Bytes>,
+    /// Extra response headers (name, value), from the `[headers]` table of the config file.
+    #[cfg(feature = "config")]
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    pub headers: Cow<'static, [(Cow<'static, str>, Cow<'static, str>)]>,
+    /// Forms of the file produced by [`CompressOptions::encoders`], keyed by content-coding or
+    /// media type. [`MiniCdnFile::variants`] iterates these after the built-in forms.
+    #[cfg(feature = "encoder")]
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    pub encoded_variants: Cow<'static, [(Cow<'static, str>, Base64Bytes)]>,
+    /// Forms of the file that were left out to save space (see [`CompressOptions::lazy`]). Use
+    /// [`MiniCdnFile::contents`] and [`MiniCdnFile::contents_gzip`] to access them regardless.
+    #[cfg(feature = "lazy")]
+    #[cfg_attr(feature = "use_serde", serde(default))]
+    pub lazy: Lazy,
 }
 
-impl EmbeddedMiniCdn {
-    /// Embeds the files into the binary at runtime, without compressing. The path is evaluated
-    /// at runtime.
-    #[cfg(feature = "walkdir")]
-    pub fn new(root_path: &str) -> Self {
-        use std::borrow::Borrow;
-        FilesystemMiniCdn::new(Cow::Owned(root_path.to_string()))
-            .borrow()
-            .into()
-    }
-
-    /// Embeds the files into the binary at runtime. The path and compression are evaluated at
-    /// runtime. This may incur significant runtime latency.
-    #[cfg(feature = "walkdir")]
-    pub fn new_compressed(root_path: &str) -> Self {
-        let mut ret = Self::default();
+/// A built-in form of a [`MiniCdnFile`], for [`MiniCdnFile::encoded`]. The variants exist
+/// regardless of features, so code using them needn't mirror minicdn's features.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// `Content-Encoding: br`.
+    Brotli,
+    /// `Content-Encoding: gzip`.
+    Gzip,
+    /// A WebP image, served as `image/webp` rather than with a `Content-Encoding`.
+    Webp,
+}
 
-        #[cfg(feature = "brotli")]
-        fn default_brotli_level() -> u8 {
-            9
+impl MiniCdnFile {
+    /// Raw bytes of file, decompressed on first access if they were left out.
+    pub fn contents(&self) -> &Base64Bytes {
+        #[cfg(feature = "lazy")]
+        if self.lazy.is_omitted() {
+            let brotli = self
+                .contents_brotli
+                .as_ref()
+                .expect("missing brotli contents");
+            return self.lazy.contents(brotli);
         }
+        &self.contents
+    }
 
-        #[cfg(feature = "brotli")]
-        fn default_brotli_buffer_size() -> usize {
-            4096
+    /// Contents compressed as GZIP, recompressed on first access if they were left out.
+    #[cfg(feature = "gzip")]
+    pub fn contents_gzip(&self) -> Option<&Base64Bytes> {
+        #[cfg(feature = "lazy")]
+        if let Some(level) = self.lazy.gzip_level() {
+            return Some(self.lazy.contents_gzip(self.contents(), level));
         }
+        self.contents_gzip.as_ref()
+    }
 
-        #[cfg(feature = "brotli")]
-        fn default_brotli_large_window_size() -> u8 {
-            20
-        }
+    /// The entity tag, if the `etag` feature is enabled.
+    pub fn etag(&self) -> Option<&str> {
+        #[cfg(feature = "etag")]
+        return Some(&self.etag);
+        #[cfg(not(feature = "etag"))]
+        None
+    }
 
-        #[cfg(feature = "gzip")]
-        fn default_gzip_level() -> u8 {
-            8
-        }
+    /// The last modified time, in seconds since the Unix epoch, if the `last_modified` feature
+    /// is enabled.
+    pub fn last_modified(&self) -> Option<&str> {
+        #[cfg(feature = "last_modified")]
+        return Some(&self.last_modified);
+        #[cfg(not(feature = "last_modified"))]
+        None
+    }
 
-        #[cfg(feature = "webp")]
-        fn default_webp_quality() -> Option<f32> {
-            Some(90.0)
-        }
+    /// The MIME type, if the `mime` feature is enabled.
+    pub fn mime(&self) -> Option<&str> {
+        #[cfg(feature = "mime")]
+        return Some(&self.mime);
+        #[cfg(not(feature = "mime"))]
+        None
+    }
 
-        #[cfg_attr(feature = "config", derive(serde::Deserialize))]
-        struct Config {
-            #[cfg(feature = "brotli")]
-            #[cfg_attr(feature = "config", serde(default = "default_brotli_level"))]
-            brotli_level: u8,
+    /// The file in the given form, if its feature is enabled and it was kept.
+    pub fn encoded(&self, encoding: Encoding) -> Option<&[u8]> {
+        match encoding {
             #[cfg(feature = "brotli")]
-            #[cfg_attr(feature = "config", serde(default = "default_brotli_buffer_size"))]
-            brotli_buffer_size: usize,
-            #[cfg(feature = "brotli")]
-            #[cfg_attr(
-                feature = "config",
-                serde(default = "default_brotli_large_window_size")
-            )]
-            brotli_large_window_size: u8,
+            Encoding::Brotli => self.contents_brotli.as_deref(),
             #[cfg(feature = "gzip")]
-            #[cfg_attr(feature = "config", serde(default = "default_gzip_level"))]
-            gzip_level: u8,
+            Encoding::Gzip => self.contents_gzip().map(|b| &**b),
             #[cfg(feature = "webp")]
-            #[cfg_attr(
-                feature = "config",
-                serde(
-                    default = "default_webp_quality",
-                    deserialize_with = "deserialize_webp_quality"
-                )
-            )]
-            webp_quality: Option<f32>,
+            Encoding::Webp => self.contents_webp.as_deref(),
+            #[allow(unreachable_patterns)]
+            _ => None,
         }
+    }
 
-        #[cfg(all(feature = "webp", feature = "config"))]
-        fn deserialize_webp_quality<'de, D: serde::de::Deserializer<'de>>(
-            deserializer: D,
-        ) -> Result<Option<f32>, D::Error> {
-            struct QualityOrLossless;
-
-            impl<'de> serde::de::Visitor<'de> for QualityOrLossless {
-                type Value = Option<f32>;
+    /// Extra response headers from the file's config, if the `config` feature is enabled.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        #[cfg(feature = "config")]
+        return self.headers.iter().map(|(name, value)| (&**name, &**value));
+        #[cfg(not(feature = "config"))]
+        std::iter::empty()
+    }
 
-                fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                    formatter.write_str("numerical quality or string \"lossless\"")
-                }
+    /// Iterates the available forms of the file other than `contents`, keyed by content-coding
+    /// (e.g. `br` or `gzip`) or, if they are a different representation altogether, media type
+    /// (e.g. `image/webp`).
+    pub fn variants(&self) -> impl Iterator<Item = (&str, &Base64Bytes)> {
+        let variants = std::iter::empty::<(&str, &Base64Bytes)>();
+        #[cfg(feature = "brotli")]
+        let variants = variants.chain(self.contents_brotli.as_ref().map(|b| ("br", b)));
+        #[cfg(feature = "gzip")]
+        let variants = variants.chain(self.contents_gzip().map(|b| ("gzip", b)));
+        #[cfg(feature = "webp")]
+        let variants = variants.chain(self.contents_webp.as_ref().map(|b| ("image/webp", b)));
+        #[cfg(feature = "encoder")]
+        let variants = variants.chain(self.encoded_variants.iter().map(|(key, b)| (&**key, b)));
+        variants
+    }
+}
 
-                fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-                where
-                    E: serde::de::Error,
-                {
-                    if value == "lossless" {
-                        Ok(None)
-                    } else {
-                        Err(E::invalid_value(
-                            serde::de::Unexpected::Str(value),
-                            &"the string \"lossless\"",
-                        ))
-                    }
-                }
+impl EmbeddedMiniCdn {
+    /// Embeds the files into the binary at runtime, without compressing. The path is evaluated
+    /// at runtime.
+    #[cfg(feature = "walkdir")]
+    pub fn new(root_path: &str) -> Self {
+        use std::borrow::Borrow;
+        FilesystemMiniCdn::new(Cow::Owned(root_path.to_string()))
+            .borrow()
+            .into()
+    }
 
-                fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
-                where
-                    E: serde::de::Error,
-                {
-                    if (0f64..=100f64).contains(&v) {
-                        Ok(Some(v as f32))
-                    } else {
-                        Err(E::invalid_value(
-                            serde::de::Unexpected::Float(v),
-                            &"a quality between 0 and 100",
-                        ))
-                    }
-                }
+    /// Embeds the files into the binary at runtime. The path and compression are evaluated at
+    /// runtime. This may incur significant runtime latency.
+    #[cfg(feature = "walkdir")]
+    pub fn new_compressed(root_path: &str) -> Self {
+        Self::new_compressed_with_options(root_path, &CompressOptions::default())
+    }
 
-                fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
-                where
-                    E: serde::de::Error,
-                {
-                    self.visit_f64(v as f64)
-                }
+    /// Like [`EmbeddedMiniCdn::new_compressed`], but with additional options.
+    #[cfg(feature = "walkdir")]
+    pub fn new_compressed_with_options(root_path: &str, options: &CompressOptions) -> Self {
+        Self::compress(root_path, options).mini_cdn
+    }
 
-                fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
-                where
-                    E: serde::de::Error,
-                {
-                    self.visit_f64(v as f64)
+    /// Like [`EmbeddedMiniCdn::new_compressed_with_options`], but also returns where each file
+    /// is served and messages for the user.
+    #[cfg(feature = "walkdir")]
+    #[allow(unused)]
+    pub fn compress(root_path: &str, options: &CompressOptions) -> Compressed {
+        let mut ret = Self::default();
+        let mut report = Vec::new();
+
+        #[allow(unused_mut, unused_variables)]
+        let (entries, mut manifest) = read_entries(root_path, options, false);
+        let paths = compressed_paths(&entries, &manifest);
+
+        // Compression is the slow part, so it may happen in parallel. Either way, files are
+        // inserted in the order returned by `get_paths`.
+        #[cfg(feature = "cache")]
+        let cache_dir = options.cache_dir.as_deref();
+        #[cfg(not(feature = "cache"))]
+        let cache_dir = None::<&std::path::Path>;
+        let compress_entry = |entry| compress_entry(entry, options, cache_dir);
+
+        #[cfg(feature = "rayon")]
+        let files = {
+            use rayon::prelude::*;
+            entries
+                .into_par_iter()
+                .map(compress_entry)
+                .collect::<Vec<_>>()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let files = entries.into_iter().map(compress_entry);
+
+        for (relative_path, file, message) in files {
+            report.extend(message);
+
+            #[cfg(feature = "fingerprint")]
+            if let Some(fingerprinted) = manifest.remove(&relative_path) {
+                if options.fingerprint_keep_original {
+                    ret.insert(Cow::Owned(relative_path.clone()), file.clone());
                 }
+                ret.insert_fingerprinted(
+                    Cow::Owned(relative_path),
+                    Cow::Owned(fingerprinted.clone()),
+                );
+                ret.insert(Cow::Owned(fingerprinted), file);
+                continue;
             }
 
-            deserializer.deserialize_any(QualityOrLossless)
+            ret.insert(Cow::Owned(relative_path), file);
         }
 
-        impl Default for Config {
-            fn default() -> Self {
-                Self {
-                    #[cfg(feature = "brotli")]
-                    brotli_level: default_brotli_level(),
-                    #[cfg(feature = "brotli")]
-                    brotli_buffer_size: default_brotli_buffer_size(),
-                    #[cfg(feature = "brotli")]
-                    brotli_large_window_size: default_brotli_large_window_size(),
-                    #[cfg(feature = "gzip")]
-                    gzip_level: default_gzip_level(),
-                    #[cfg(feature = "webp")]
-                    webp_quality: default_webp_quality(),
-                }
-            }
+        Compressed {
+            mini_cdn: ret,
+            paths,
+            report,
         }
+    }
 
-        #[cfg(feature = "config")]
-        let mut configs = HashMap::<String, Config>::new();
-
-        get_paths(root_path).for_each(|(absolute_path, relative_path)| {
-            let contents = std::fs::read(&absolute_path).expect(&relative_path);
-
-            #[cfg(feature = "config")]
-            if let Some(name) = relative_path.strip_suffix(CONFIG_SUFFIX) {
-                let config: Config = toml::from_slice(&contents).expect(&relative_path);
-                configs.insert(name.to_owned(), config);
-                return;
-            }
-
-            #[cfg(feature = "last_modified")]
-            let last_modified = last_modified(&absolute_path);
-            #[cfg(any(feature = "mime", feature = "webp"))]
-            let mime = mime(&relative_path);
-            #[cfg(feature = "etag")]
-            let etag = etag(&contents);
-
-            #[cfg(feature = "config")]
-            #[allow(unused)]
-            let config = configs
-                .remove({
-                    if let Some((before, after)) = relative_path.split_once('.') {
-                        before
-                    } else {
-                        &relative_path
-                    }
-                })
-                .unwrap_or_default();
-            #[cfg(not(feature = "config"))]
-            #[allow(unused)]
-            let config = Config::default();
-
-            #[cfg(feature = "webp")]
-            let contents_webp = webp(&contents, &mime, config.webp_quality);
-
-            #[cfg(not(feature = "webp"))]
-            #[allow(unused)]
-            let special = false;
-
-            #[cfg(feature = "webp")]
-            #[allow(unused)]
-            let special = contents_webp.is_some();
-
-            #[cfg(feature = "gzip")]
-            let contents_gzip = if special {
-                None
-            } else {
-                gzip(&contents, config.gzip_level)
-            };
-
-            #[cfg(feature = "brotli")]
-            let contents_brotli = if special {
-                None
-            } else {
-                brotli(
-                    &contents,
-                    config.brotli_buffer_size,
-                    config.brotli_level,
-                    config.brotli_large_window_size,
-                )
-            };
-
-            ret.insert(
-                Cow::Owned(relative_path),
-                MiniCdnFile {
-                    #[cfg(feature = "etag")]
-                    etag: etag.into(),
-                    #[cfg(feature = "last_modified")]
-                    last_modified: last_modified.into(),
-                    #[cfg(feature = "mime")]
-                    mime: mime.into(),
-                    contents: contents.into(),
-                    #[cfg(feature = "brotli")]
-                    contents_brotli: contents_brotli.map(Into::into),
-                    #[cfg(feature = "gzip")]
-                    contents_gzip: contents_gzip.map(Into::into),
-                    #[cfg(feature = "webp")]
-                    contents_webp: contents_webp.map(Into::into),
-                },
-            );
-        });
-
-        #[cfg(feature = "config")]
-        assert!(
-            configs.is_empty(),
-            "unused minicdn config files: {:?}",
-            configs.keys().collect::<Vec<_>>()
-        );
-
-        ret
+    /// Determines which path each file would be served at by
+    /// [`EmbeddedMiniCdn::new_compressed_with_options`], keyed by the path before
+    /// fingerprinting, without compressing anything.
+    #[cfg(feature = "walkdir")]
+    pub fn compressed_paths(
+        root_path: &str,
+        options: &CompressOptions,
+    ) -> std::collections::BTreeMap<String, CompressedPath> {
+        // Without fingerprints, the paths don't depend on the contents.
+        #[cfg(feature = "fingerprint")]
+        let paths_only = !options.fingerprint;
+        #[cfg(not(feature = "fingerprint"))]
+        let paths_only = true;
+
+        let (entries, manifest) = read_entries(root_path, options, paths_only);
+        compressed_paths(&entries, &manifest)
     }
 
     /// Gets a previously embedded or inserted file.
@@ -323,6 +421,62 @@ impl EmbeddedMiniCdn {
     pub fn iter(&self) -> impl Iterator<Item = (&Cow<'_, str>, &MiniCdnFile)> {
         self.files.iter()
     }
+
+    /// Gets the fingerprinted path of a file, given its original path.
+    pub fn fingerprinted(&self, path: &str) -> Option<&str> {
+        self.manifest
+            .get(path)
+            .map(|fingerprinted| &**fingerprinted)
+    }
+
+    /// Records the fingerprinted path of a file. Does not insert the file itself.
+    pub fn insert_fingerprinted(
+        &mut self,
+        path: Cow<'static, str>,
+        fingerprinted: Cow<'static, str>,
+    ) {
+        self.manifest.insert(path, fingerprinted);
+    }
+
+    /// Iterates (original path, fingerprinted path) pairs.
+    pub fn manifest(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.manifest
+            .iter()
+            .map(|(path, fingerprinted)| (&**path, &**fingerprinted))
+    }
+}
+
+#[cfg(feature = "phf")]
+impl StaticMiniCdn {
+    #[doc(hidden)]
+    pub const fn new(
+        files: phf::Map<&'static str, &'static MiniCdnFile>,
+        manifest: phf::Map<&'static str, &'static str>,
+    ) -> Self {
+        Self { files, manifest }
+    }
+
+    /// Gets an embedded file.
+    pub fn get(&self, path: &str) -> Option<&'static MiniCdnFile> {
+        self.files.get(path).copied()
+    }
+
+    /// Iterates the embedded files.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &'static MiniCdnFile)> + '_ {
+        self.files.entries().map(|(path, file)| (*path, *file))
+    }
+
+    /// Gets the fingerprinted path of a file, given its original path.
+    pub fn fingerprinted(&self, path: &str) -> Option<&str> {
+        self.manifest.get(path).copied()
+    }
+
+    /// Iterates (original path, fingerprinted path) pairs.
+    pub fn manifest(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.manifest
+            .entries()
+            .map(|(path, fingerprinted)| (*path, *fingerprinted))
+    }
 }
 
 #[cfg(feature = "walkdir")]
@@ -333,31 +487,54 @@ impl FilesystemMiniCdn {
         Self { root_path }
     }
 
-    /// Loads a file from the corresponding directory.
+    /// Loads a file from the corresponding directory. Like
+    /// [`EmbeddedMiniCdn::new_compressed_with_options`], Markdown files are rendered and Sass
+    /// files compiled, and served at their new paths.
+    ///
+    /// Unlike at compile time, errors (e.g. a malformed config file) don't panic, as this may be
+    /// called by a server. The affected files are treated as missing instead.
+    #[allow(unused_variables)]
     pub fn get(&self, path: &str) -> Option<MiniCdnFile> {
-        #[cfg(feature = "config")]
-        if path.ends_with(CONFIG_SUFFIX) {
-            // Though we don't expect to be asked for the config file,
-            // make sure we never return it.
-            return None;
+        let root_config = self.root_config()?;
+        let (source_path, canonical_path_tmp) = self.source(path, &root_config)?;
+        let canonical_path = canonical_path_tmp.to_str()?;
+        #[allow(unused_mut)]
+        let mut contents = std::fs::read(canonical_path).ok()?;
+
+        #[cfg(feature = "markdown")]
+        if source_path != path && markdown::is_markdown(&source_path) {
+            let layout = match &root_config.markdown_layout {
+                Some(layout_path) => {
+                    let layout_path =
+                        std::path::Path::new(self.root_path.as_ref()).join(layout_path);
+                    Some(std::fs::read_to_string(layout_path).ok()?)
+                }
+                None => None,
+            };
+            contents = markdown::render(&source_path, &contents, layout.as_deref()).ok()?;
         }
 
-        let canonical_path_tmp = std::path::Path::new(self.root_path.as_ref())
-            .join(path)
-            .canonicalize()
-            .ok()?;
-        let canonical_path = canonical_path_tmp.to_str()?;
-        let canonical_root_path_tmp = std::path::Path::new(self.root_path.as_ref())
-            .canonicalize()
-            .ok()?;
-        let canonical_root_path = canonical_root_path_tmp.to_str()?;
-        if !canonical_path.starts_with(canonical_root_path) {
-            return None;
+        #[cfg(feature = "sass")]
+        if sass::is_scss(&source_path) {
+            contents = sass::compile(canonical_path, &contents).ok()?;
         }
-        let contents = std::fs::read(&canonical_path).ok()?;
+
+        #[cfg(feature = "config")]
+        let config = {
+            let config_path = std::path::Path::new(self.root_path.as_ref()).join(format!(
+                "{}{}",
+                config::config_name(path),
+                CONFIG_SUFFIX
+            ));
+            match std::fs::read(&config_path) {
+                Ok(config) => Config::parse(&config).ok()?,
+                Err(_) => Config::default(),
+            }
+        };
+
         Some(MiniCdnFile {
             #[cfg(feature = "mime")]
-            mime: mime(canonical_path).into(),
+            mime: mime(path).into(),
             #[cfg(feature = "etag")]
             etag: etag(&contents).into(),
             #[cfg(feature = "last_modified")]
@@ -369,15 +546,129 @@ impl FilesystemMiniCdn {
             contents_gzip: None,
             #[cfg(feature = "webp")]
             contents_webp: None,
+            #[cfg(feature = "config")]
+            headers: headers(config.headers),
+            #[cfg(feature = "encoder")]
+            encoded_variants: Cow::Borrowed(&[]),
+            #[cfg(feature = "lazy")]
+            lazy: Lazy::default(),
         })
     }
 
-    /// Iterate files in the corresponding directory, without compressing.
+    /// Whether [`FilesystemMiniCdn::get`] would find a file, without loading it.
+    pub fn contains(&self, path: &str) -> bool {
+        self.root_config()
+            .is_some_and(|root_config| self.source(path, &root_config).is_some())
+    }
+
+    /// The root config, read on every access so that changes apply immediately. `None` if it is
+    /// malformed.
+    fn root_config(&self) -> Option<RootConfig> {
+        #[cfg(feature = "config")]
+        {
+            let config_path = std::path::Path::new(self.root_path.as_ref()).join(CONFIG_SUFFIX);
+            if let Ok(config) = std::fs::read(config_path) {
+                return RootConfig::parse(&config).ok();
+            }
+        }
+        Some(RootConfig::default())
+    }
+
+    /// The relative and canonical path of the file that `path` is served from, which differs
+    /// from `path` for rendered Markdown and compiled Sass.
+    #[allow(unused_variables)]
+    fn source(&self, path: &str, root_config: &RootConfig) -> Option<(String, std::path::PathBuf)> {
+        let file = |path: String| {
+            let canonical_path = self.canonical_path(&path)?;
+            canonical_path.is_file().then_some((path, canonical_path))
+        };
+
+        // Only used to produce other files.
+        #[cfg(feature = "markdown")]
+        if root_config.markdown_layout.as_deref() == Some(path)
+            || (markdown::is_markdown(path) && !root_config.markdown_keep_original)
+        {
+            return None;
+        }
+        #[cfg(feature = "sass")]
+        if sass::is_scss(path) {
+            return None;
+        }
+
+        if let Some(found) = file(path.to_owned()) {
+            return Some(found);
+        }
+
+        #[cfg(feature = "markdown")]
+        if let Some(stem) = path.strip_suffix(".html") {
+            if let Some(found) = ["md", "markdown"]
+                .into_iter()
+                .find_map(|extension| file(format!("{}.{}", stem, extension)))
+            {
+                return Some(found);
+            }
+        }
+        #[cfg(feature = "sass")]
+        if let Some(stem) = path.strip_suffix(".css") {
+            let scss_path = format!("{}.scss", stem);
+            if !sass::is_partial(&scss_path) {
+                return file(scss_path);
+            }
+        }
+        None
+    }
+
+    /// The canonical path of a file, if it exists within the directory and may be served.
+    fn canonical_path(&self, path: &str) -> Option<std::path::PathBuf> {
+        #[cfg(feature = "config")]
+        if path.ends_with(CONFIG_SUFFIX) {
+            // Though we don't expect to be asked for the config file,
+            // make sure we never return it.
+            return None;
+        }
+
+        let canonical_path = std::path::Path::new(self.root_path.as_ref())
+            .join(path)
+            .canonicalize()
+            .ok()?;
+        let canonical_root_path = std::path::Path::new(self.root_path.as_ref())
+            .canonicalize()
+            .ok()?;
+        canonical_path
+            .to_str()?
+            .starts_with(canonical_root_path.to_str()?)
+            .then_some(canonical_path)
+    }
+
+    /// Iterate files in the corresponding directory, without compressing, at the paths
+    /// [`FilesystemMiniCdn::get`] serves them at.
     pub fn iter(&self) -> impl Iterator<Item = (String, MiniCdnFile)> + '_ {
-        get_paths(&self.root_path).filter_map(|(_, relative)| {
-            let file = self.get(&relative)?;
-            Some((relative, file))
-        })
+        let root_config = self.root_config();
+        get_paths(&self.root_path)
+            .flat_map(move |(_, relative)| {
+                #[allow(unused_mut)]
+                let mut paths = vec![relative.clone()];
+                #[cfg(feature = "markdown")]
+                if markdown::is_markdown(&relative) {
+                    paths.push(markdown::html_path(&relative));
+                }
+                #[cfg(feature = "sass")]
+                if sass::is_scss(&relative) {
+                    paths.push(sass::css_path(&relative));
+                }
+                // Skips files that are served from elsewhere, or not at all.
+                paths.retain(|path| {
+                    root_config
+                        .as_ref()
+                        .and_then(|root_config| self.source(path, root_config))
+                        .is_some_and(|(source_path, _)| source_path == relative)
+                });
+                paths
+            })
+            .filter_map(|path| {
+                let file = self.get(&path)?;
+                Some((path, file))
+            })
     }
 }
 
@@ -409,6 +700,24 @@ impl MiniCdn {
             Self::Embedded(embedded) => embedded.get(path).map(Cow::Borrowed),
             #[cfg(feature = "walkdir")]
             Self::Filesystem(filesystem) => filesystem.get(path).map(Cow::Owned),
+            #[cfg(feature = "phf")]
+            Self::Static(files) => files.get(path).map(Cow::Borrowed),
+        }
+    }
+
+    /// Get the path a file should be linked as, which differs from the original path if it was
+    /// fingerprinted.
+    pub fn fingerprinted<'a>(&'a self, path: &'a str) -> Option<&'a str> {
+        match self {
+            Self::Embedded(embedded) => embedded
+                .fingerprinted(path)
+                .or_else(|| embedded.get(path).map(|_| path)),
+            #[cfg(feature = "walkdir")]
+            Self::Filesystem(filesystem) => filesystem.contains(path).then_some(path),
+            #[cfg(feature = "phf")]
+            Self::Static(files) => files
+                .fingerprinted(path)
+                .or_else(|| files.get(path).map(|_| path)),
         }
     }
 
@@ -421,6 +730,11 @@ impl MiniCdn {
                 *self = Self::Embedded((&*filesystem).into());
                 self.insert(path, file);
             }
+            #[cfg(feature = "phf")]
+            Self::Static(files) => {
+                *self = Self::Embedded((*files).into());
+                self.insert(path, file);
+            }
         }
     }
 
@@ -432,6 +746,8 @@ impl MiniCdn {
             Self::Filesystem(filesystem) => {
                 filesystem.iter().for_each(|(path, file)| f(&path, &file))
             }
+            #[cfg(feature = "phf")]
+            Self::Static(files) => files.iter().for_each(|(path, file)| f(path, file)),
         }
     }
 }
@@ -447,6 +763,712 @@ impl From<&FilesystemMiniCdn> for EmbeddedMiniCdn {
     }
 }
 
+#[cfg(all(feature = "phf", feature = "use_serde"))]
+impl serde::Serialize for StaticMiniCdn {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Same format as EmbeddedMiniCdn.
+        EmbeddedMiniCdn::from(self).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "phf")]
+impl From<&StaticMiniCdn> for EmbeddedMiniCdn {
+    fn from(files: &StaticMiniCdn) -> Self {
+        let mut ret = EmbeddedMiniCdn::default();
+        for (existing_path, existing_file) in files.iter() {
+            ret.insert(Cow::Owned(existing_path.to_owned()), existing_file.clone());
+        }
+        for (path, fingerprinted) in files.manifest() {
+            ret.insert_fingerprinted(
+                Cow::Owned(path.to_owned()),
+                Cow::Owned(fingerprinted.to_owned()),
+            );
+        }
+        ret
+    }
+}
+
+/// Where a file would be served, from [`EmbeddedMiniCdn::compressed_paths`].
+#[cfg(feature = "walkdir")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedPath {
+    /// The path of the file on disk it is produced from, relative to the root. This differs from
+    /// the path it is served at if it was renamed, e.g. `style.scss` for `style.css`.
+    pub source: String,
+    /// The path it is served at, which is fingerprinted if the file is.
+    pub served: String,
+}
+
+/// Looks up the path a file is served at in a table generated by `include_mini_cdn_module!`.
+/// A `const fn`, so that a missing file can fail compilation.
+#[doc(hidden)]
+pub const fn find_asset(assets: &[(&str, &'static str)], path: &str) -> Option<&'static str> {
+    let mut i = 0;
+    while i < assets.len() {
+        let (key, served) = assets[i];
+        if key.len() == path.len() {
+            let mut j = 0;
+            while j < key.len() && key.as_bytes()[j] == path.as_bytes()[j] {
+                j += 1;
+            }
+            if j == key.len() {
+                return Some(served);
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The result of [`EmbeddedMiniCdn::compress`].
+#[cfg(feature = "walkdir")]
+#[derive(Clone, Debug)]
+pub struct Compressed {
+    /// The files.
+    pub mini_cdn: EmbeddedMiniCdn,
+    /// Where each file is served, keyed by the path before fingerprinting (see
+    /// [`EmbeddedMiniCdn::compressed_paths`]).
+    pub paths: std::collections::BTreeMap<String, CompressedPath>,
+    /// Messages for the user, such as which parameters `auto` chose for each file.
+    pub report: Vec<String>,
+}
+
+/// Pairs each entry's path before fingerprinting with its source and the path it is served at.
+#[cfg(feature = "walkdir")]
+fn compressed_paths(
+    entries: &[Entry],
+    manifest: &HashMap<String, String>,
+) -> std::collections::BTreeMap<String, CompressedPath> {
+    entries
+        .iter()
+        .map(|entry| {
+            let served = manifest
+                .get(&entry.relative_path)
+                .unwrap_or(&entry.relative_path);
+            let path = CompressedPath {
+                source: entry.source_path.clone(),
+                served: served.clone(),
+            };
+            (entry.relative_path.clone(), path)
+        })
+        .collect()
+}
+
+/// A file to be embedded, along with its config.
+#[cfg(feature = "walkdir")]
+struct Entry {
+    absolute_path: String,
+    /// The path of the file on disk it was produced from, relative to the root.
+    source_path: String,
+    /// The path to serve it at (before fingerprinting), which may differ from `source_path`
+    /// (e.g. for compiled Sass or generated files).
+    relative_path: String,
+    contents: Vec<u8>,
+    config: Config,
+}
+
+/// Compresses a file, returning its relative path, the result, and what `auto` chose, if
+/// anything.
+#[cfg(feature = "walkdir")]
+#[allow(unused_variables)]
+fn compress_entry(
+    entry: Entry,
+    options: &CompressOptions,
+    cache_dir: Option<&std::path::Path>,
+) -> (String, MiniCdnFile, Option<String>) {
+    #[allow(unused_variables)]
+    let Entry {
+        absolute_path,
+        relative_path,
+        contents,
+        config,
+        ..
+    } = entry;
+
+    #[cfg(feature = "last_modified")]
+    let last_modified = last_modified(&absolute_path);
+    #[cfg(any(feature = "mime", feature = "webp", feature = "encoder"))]
+    let mime = mime(&relative_path);
+    #[cfg(feature = "etag")]
+    let etag = etag(&contents);
+
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "webp",
+        feature = "encoder"
+    ))]
+    let threshold = config
+        .compression_threshold
+        .or(options.compression_threshold)
+        .unwrap_or(0.9);
+
+    #[cfg(feature = "webp")]
+    let contents_webp = if config.webp {
+        cached(
+            cache_dir,
+            "webp",
+            &format!("{} {:?}", mime, config.webp_quality),
+            &contents,
+            || webp(&contents, &mime, config.webp_quality),
+        )
+        .filter(|output| worthwhile(output, &contents, threshold))
+    } else {
+        None
+    };
+
+    #[cfg(not(feature = "webp"))]
+    #[allow(unused)]
+    let special = false;
+
+    #[cfg(feature = "webp")]
+    #[allow(unused)]
+    let special = contents_webp.is_some();
+
+    // What `auto` chose, if anything.
+    #[allow(unused_mut)]
+    let mut report = Vec::<String>::new();
+
+    #[cfg(feature = "gzip")]
+    #[allow(unused_mut)]
+    let mut contents_gzip = if special || config.gzip == Some(false) {
+        None
+    } else {
+        compress_gzip(&contents, &config, cache_dir, &mut report)
+            .filter(|output| worthwhile(output, &contents, threshold))
+    };
+
+    #[cfg(feature = "brotli")]
+    let contents_brotli = if special || config.brotli == Some(false) {
+        None
+    } else {
+        compress_brotli(&contents, &config, cache_dir, &mut report)
+            .filter(|output| worthwhile(output, &contents, threshold))
+    };
+
+    let report =
+        (!report.is_empty()).then(|| format!("minicdn: {}: {}", relative_path, report.join(", ")));
+
+    #[cfg(feature = "encoder")]
+    let variants = options
+        .encoders
+        .iter()
+        .filter_map(|encoder| {
+            let output = encoder
+                .encode(&mime, &contents)
+                .filter(|output| worthwhile(output, &contents, threshold))?;
+            Some((Cow::Owned(encoder.key().to_owned()), output.into()))
+        })
+        .collect::<Vec<_>>();
+
+    // GZIP from zopfli or `auto` can't be reproduced from the level alone, so is kept.
+    #[cfg(all(feature = "lazy", feature = "gzip"))]
+    let gzip_reproducible = !config.auto;
+    #[cfg(all(feature = "lazy", feature = "zopfli"))]
+    let gzip_reproducible =
+        gzip_reproducible && matches!(config.gzip_algorithm, config::GzipAlgorithm::Flate2);
+
+    #[cfg(feature = "lazy")]
+    let lazy = match &contents_brotli {
+        Some(_) if options.lazy => Lazy::new(
+            true,
+            #[cfg(feature = "gzip")]
+            contents_gzip
+                .take_if(|_| gzip_reproducible)
+                .map(|_| config.gzip_level),
+        ),
+        _ => Lazy::default(),
+    };
+    #[cfg(feature = "lazy")]
+    let contents = if lazy.is_omitted() {
+        Vec::new()
+    } else {
+        contents
+    };
+
+    let file = MiniCdnFile {
+        #[cfg(feature = "etag")]
+        etag: etag.into(),
+        #[cfg(feature = "last_modified")]
+        last_modified: last_modified.into(),
+        #[cfg(feature = "mime")]
+        mime: mime.into(),
+        contents: contents.into(),
+        #[cfg(feature = "brotli")]
+        contents_brotli: contents_brotli.map(Into::into),
+        #[cfg(feature = "gzip")]
+        contents_gzip: contents_gzip.map(Into::into),
+        #[cfg(feature = "webp")]
+        contents_webp: contents_webp.map(Into::into),
+        #[cfg(feature = "config")]
+        headers: headers(config.headers),
+        #[cfg(feature = "encoder")]
+        encoded_variants: Cow::Owned(variants),
+        #[cfg(feature = "lazy")]
+        lazy,
+    };
+
+    (relative_path, file, report)
+}
+
+/// Compresses with the configured Brotli parameters, or the best ones found by `auto`, whose
+/// choice is added to `report`.
+#[cfg(all(feature = "brotli", feature = "walkdir"))]
+fn compress_brotli(
+    contents: &[u8],
+    config: &Config,
+    cache_dir: Option<&std::path::Path>,
+    report: &mut Vec<String>,
+) -> Option<Vec<u8>> {
+    let configured = (config.brotli_level, config.brotli_large_window_size);
+    if config.auto {
+        // The choice is stored before the output, so it can be reported on a cache hit.
+        let chosen = cached(
+            cache_dir,
+            "brotli auto",
+            &format!(
+                "{} {:?} {} {:?}",
+                config.brotli_buffer_size,
+                configured,
+                config.auto_candidates,
+                config.auto_budget_ms
+            ),
+            contents,
+            || {
+                let ((level, window), output) = auto::search(
+                    auto::brotli_candidates(configured),
+                    config.auto_candidates,
+                    config.auto_budget_ms.map(std::time::Duration::from_millis),
+                    |(level, window)| brotli(contents, config.brotli_buffer_size, level, window),
+                );
+                Some([&[level, window][..], &output].concat())
+            },
+        )?;
+        let ([level, window], output) = chosen.split_first_chunk::<2>()?;
+        report.push(format!(
+            "brotli_level = {}, brotli_large_window_size = {}",
+            level, window
+        ));
+        return Some(output.to_vec());
+    }
+    cached(
+        cache_dir,
+        "brotli",
+        &format!(
+            "{} {} {}",
+            config.brotli_buffer_size, config.brotli_level, config.brotli_large_window_size
+        ),
+        contents,
+        || {
+            Some(brotli(
+                contents,
+                config.brotli_buffer_size,
+                config.brotli_level,
+                config.brotli_large_window_size,
+            ))
+        },
+    )
+}
+
+/// Compresses with the configured GZIP algorithm and level, or the best level found by `auto`,
+/// whose choice is added to `report`.
+#[cfg(all(feature = "gzip", feature = "walkdir"))]
+fn compress_gzip(
+    contents: &[u8],
+    config: &Config,
+    cache_dir: Option<&std::path::Path>,
+    report: &mut Vec<String>,
+) -> Option<Vec<u8>> {
+    #[cfg(feature = "zopfli")]
+    if let config::GzipAlgorithm::Zopfli = config.gzip_algorithm {
+        return cached(
+            cache_dir,
+            "zopfli",
+            &config.zopfli_iterations.to_string(),
+            contents,
+            || Some(zopfli(contents, config.zopfli_iterations)),
+        );
+    }
+    if config.auto {
+        // The choice is stored before the output, so it can be reported on a cache hit.
+        let chosen = cached(
+            cache_dir,
+            "gzip auto",
+            &format!(
+                "{} {} {:?}",
+                config.gzip_level, config.auto_candidates, config.auto_budget_ms
+            ),
+            contents,
+            || {
+                let (level, output) = auto::search(
+                    auto::gzip_candidates(config.gzip_level),
+                    config.auto_candidates,
+                    config.auto_budget_ms.map(std::time::Duration::from_millis),
+                    |level| gzip(contents, level),
+                );
+                Some([&[level][..], &output].concat())
+            },
+        )?;
+        let (level, output) = chosen.split_first()?;
+        report.push(format!("gzip_level = {}", level));
+        return Some(output.to_vec());
+    }
+    cached(
+        cache_dir,
+        "gzip",
+        &config.gzip_level.to_string(),
+        contents,
+        || Some(gzip(contents, config.gzip_level)),
+    )
+}
+
+/// Runs `compress`, unless its output (for the same `encoder`, `parameters` and `contents`) is
+/// in the cache.
+#[cfg(feature = "walkdir")]
+#[allow(dead_code, unused_variables)]
+fn cached(
+    cache_dir: Option<&std::path::Path>,
+    encoder: &str,
+    parameters: &str,
+    contents: &[u8],
+    compress: impl FnOnce() -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    #[cfg(feature = "cache")]
+    if let Some(cache_dir) = cache_dir {
+        return cache::get_or_insert_with(cache_dir, encoder, parameters, contents, compress);
+    }
+    compress()
+}
+
+/// Reads the files to be embedded, pairing them with their configs, and determines which paths
+/// they will be served at (original path to fingerprinted path).
+///
+/// If `paths_only`, processing that can't affect the paths (e.g. minification) is skipped, so
+/// the contents are incomplete.
+#[cfg(feature = "walkdir")]
+#[allow(unused_variables)]
+fn read_entries(
+    root_path: &str,
+    options: &CompressOptions,
+    paths_only: bool,
+) -> (Vec<Entry>, HashMap<String, String>) {
+    #[cfg(any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "webp",
+        feature = "encoder"
+    ))]
+    if let Some(threshold) = options.compression_threshold {
+        assert!(
+            (0.0..=1.0).contains(&threshold),
+            "CompressOptions::compression_threshold must be between 0 and 1, got {}",
+            threshold
+        );
+    }
+    #[cfg(feature = "config")]
+    let mut configs = HashMap::<String, Config>::new();
+    #[cfg(all(feature = "config", feature = "transform"))]
+    let mut config_tables = HashMap::<String, toml::value::Table>::new();
+    // Sorted before everything else, if present.
+    #[allow(unused_mut)]
+    let mut root_config = RootConfig::default();
+
+    #[cfg(feature = "markdown")]
+    #[allow(unused_mut)]
+    let mut layout = None::<String>;
+
+    // Generated alongside other files (e.g. source maps), and not present on disk.
+    #[cfg(any(feature = "minify_js", feature = "transform", feature = "markdown"))]
+    let mut generated = Vec::<Entry>::new();
+
+    #[allow(unused_mut)]
+    let mut entries = get_paths(root_path)
+        .filter_map(|(absolute_path, relative_path)| {
+            let contents = std::fs::read(&absolute_path).expect(&relative_path);
+
+            #[cfg(feature = "config")]
+            if relative_path == CONFIG_SUFFIX {
+                root_config = RootConfig::parse(&contents).expect(&relative_path);
+                // The root config is read first, so the layout is ready for any Markdown file.
+                #[cfg(feature = "markdown")]
+                if let Some(layout_path) = &root_config.markdown_layout {
+                    let layout_path = std::path::Path::new(root_path).join(layout_path);
+                    layout = Some(std::fs::read_to_string(&layout_path).unwrap_or_else(|e| {
+                        panic!("failed to read Markdown layout {:?}: {}", layout_path, e)
+                    }));
+                }
+                return None;
+            }
+
+            #[cfg(feature = "markdown")]
+            if root_config.markdown_layout.as_ref() == Some(&relative_path) {
+                return None;
+            }
+
+            #[cfg(feature = "config")]
+            if let Some(name) = relative_path.strip_suffix(CONFIG_SUFFIX) {
+                let config = Config::parse(&contents).expect(&relative_path);
+                #[cfg(feature = "transform")]
+                config_tables.insert(
+                    name.to_owned(),
+                    toml::from_slice(&contents).expect(&relative_path),
+                );
+                configs.insert(name.to_owned(), config);
+                return None;
+            }
+
+            let source_path = relative_path.clone();
+
+            #[cfg(all(feature = "config", feature = "transform"))]
+            let config_table = config_tables
+                .remove(config::config_name(&relative_path))
+                .unwrap_or_default();
+            #[cfg(feature = "config")]
+            let config = configs
+                .remove(config::config_name(&relative_path))
+                .unwrap_or_default();
+            #[cfg(not(feature = "config"))]
+            let config = Config::default();
+
+            // Partials are compiled into the files that import them.
+            #[cfg(feature = "sass")]
+            if sass::is_partial(&relative_path) {
+                return None;
+            }
+
+            #[cfg(feature = "transform")]
+            let (relative_path, contents) = {
+                let mut relative_path = relative_path;
+                let mut contents = contents;
+                for transform in &options.transforms {
+                    let output = transform.transform(TransformInput {
+                        absolute_path: &absolute_path,
+                        relative_path: &relative_path,
+                        mime: &mime(&relative_path),
+                        contents,
+                        #[cfg(feature = "config")]
+                        config: TransformConfig(&config_table),
+                    });
+                    relative_path = output.relative_path;
+                    contents = output.contents;
+                    for (extra_path, extra_contents) in output.extra_files {
+                        generated.push(Entry {
+                            absolute_path: absolute_path.clone(),
+                            source_path: source_path.clone(),
+                            relative_path: extra_path,
+                            contents: extra_contents,
+                            config: Config {
+                                #[cfg(feature = "fingerprint")]
+                                fingerprint: false,
+                                ..config.clone()
+                            },
+                        });
+                    }
+                }
+                (relative_path, contents)
+            };
+
+            #[cfg(feature = "markdown")]
+            let (relative_path, contents) = if markdown::is_markdown(&relative_path) {
+                let html = if paths_only {
+                    Vec::new()
+                } else {
+                    markdown::render(&relative_path, &contents, layout.as_deref())
+                        .unwrap_or_else(|e| panic!("{}", e))
+                };
+                if root_config.markdown_keep_original {
+                    generated.push(Entry {
+                        absolute_path: absolute_path.clone(),
+                        source_path: source_path.clone(),
+                        relative_path: relative_path.clone(),
+                        contents,
+                        config: config.clone(),
+                    });
+                }
+                (markdown::html_path(&relative_path), html)
+            } else {
+                (relative_path, contents)
+            };
+
+            #[cfg(feature = "sass")]
+            let (relative_path, contents) = if sass::is_scss(&relative_path) {
+                let css = if paths_only {
+                    Vec::new()
+                } else {
+                    sass::compile(&absolute_path, &contents).unwrap_or_else(|e| panic!("{}", e))
+                };
+                (sass::css_path(&relative_path), css)
+            } else {
+                (relative_path, contents)
+            };
+
+            // Before anything that depends on the contents, like the etag.
+            #[cfg(any(
+                feature = "minify_html",
+                feature = "minify_css",
+                feature = "minify_js",
+                feature = "minify_json",
+                feature = "minify_svg"
+            ))]
+            #[cfg_attr(not(feature = "minify_js"), allow(unused_variables))]
+            let (contents, source_map) = if config.minify && !paths_only {
+                minify::minify(
+                    &absolute_path,
+                    &relative_path,
+                    &mime(&relative_path),
+                    contents,
+                    &config,
+                    &root_config,
+                )
+            } else {
+                (contents, None)
+            };
+            #[cfg(feature = "minify_js")]
+            if config.minify && config.source_map && minify::is_js(&mime(&relative_path)) {
+                generated.push(Entry {
+                    absolute_path: absolute_path.clone(),
+                    source_path: source_path.clone(),
+                    relative_path: format!("{}.map", relative_path),
+                    // Empty if only the paths are needed.
+                    contents: source_map.unwrap_or_default(),
+                    // The minified file refers to the map by its original path.
+                    config: Config {
+                        #[cfg(feature = "fingerprint")]
+                        fingerprint: false,
+                        ..Config::default()
+                    },
+                });
+            }
+
+            Some(Entry {
+                absolute_path,
+                source_path,
+                relative_path,
+                contents,
+                config,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    #[cfg(any(feature = "minify_js", feature = "transform", feature = "markdown"))]
+    entries.extend(generated);
+
+    // Including generated files, by the MIME type they are served with. An explicit per-file
+    // setting wins.
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    for entry in &mut entries {
+        let compress = !root_config.is_incompressible(&mime(&entry.relative_path));
+        #[cfg(feature = "brotli")]
+        entry.config.brotli.get_or_insert(compress);
+        #[cfg(feature = "gzip")]
+        entry.config.gzip.get_or_insert(compress);
+    }
+
+    // Files may have been renamed or generated.
+    let mut relative_paths = std::collections::HashSet::new();
+    for entry in &entries {
+        assert!(
+            relative_paths.insert(&entry.relative_path),
+            "multiple files would be served at {}",
+            entry.relative_path
+        );
+    }
+
+    #[cfg(feature = "config")]
+    assert!(
+        configs.is_empty(),
+        "unused minicdn config files: {:?}",
+        configs.keys().collect::<Vec<_>>()
+    );
+
+    #[allow(unused_mut)]
+    let mut manifest = HashMap::<String, String>::new();
+
+    #[cfg(feature = "fingerprint")]
+    if options.fingerprint {
+        #[cfg(feature = "rewrite")]
+        let (mut rewritable, others): (Vec<_>, Vec<_>) = entries.iter_mut().partition(|entry| {
+            options.fingerprint_rewrite && rewrite::is_rewritable(&mime(&entry.relative_path))
+        });
+        #[cfg(not(feature = "rewrite"))]
+        let others = entries.iter_mut();
+
+        for entry in others {
+            if entry.config.fingerprint {
+                let fingerprinted = fingerprint(&entry.relative_path, &etag(&entry.contents));
+                manifest.insert(entry.relative_path.clone(), fingerprinted);
+            }
+        }
+
+        // Rewritable files may reference each other, so repeat until their fingerprints
+        // settle. A file whose references lead back to itself would never settle, so isn't
+        // fingerprinted.
+        #[cfg(feature = "rewrite")]
+        {
+            let paths = rewritable
+                .iter()
+                .filter(|entry| entry.config.fingerprint)
+                .map(|entry| entry.relative_path.clone())
+                .collect();
+            let references = rewritable
+                .iter()
+                .map(|entry| {
+                    let references = rewrite::references(
+                        &entry.relative_path,
+                        &mime(&entry.relative_path),
+                        &entry.contents,
+                        &paths,
+                    );
+                    (entry.relative_path.clone(), references)
+                })
+                .collect();
+            for entry in rewritable.iter_mut() {
+                if rewrite::is_cyclic(&entry.relative_path, &references) {
+                    entry.config.fingerprint = false;
+                }
+            }
+
+            let originals = rewritable
+                .iter_mut()
+                .map(|entry| std::mem::take(&mut entry.contents))
+                .collect::<Vec<_>>();
+            for iteration in 0..=rewritable.len() {
+                let mut changed = false;
+                for (entry, original) in rewritable.iter_mut().zip(&originals) {
+                    entry.contents = rewrite::rewrite(
+                        &entry.relative_path,
+                        &mime(&entry.relative_path),
+                        original,
+                        &manifest,
+                    );
+                    if entry.config.fingerprint {
+                        let fingerprinted =
+                            fingerprint(&entry.relative_path, &etag(&entry.contents));
+                        changed |= manifest.get(&entry.relative_path) != Some(&fingerprinted);
+                        manifest.insert(entry.relative_path.clone(), fingerprinted);
+                    }
+                }
+                if !changed {
+                    break;
+                }
+                assert!(
+                    iteration < rewritable.len(),
+                    "fingerprints of rewritten files didn't settle"
+                );
+            }
+        }
+    }
+    (entries, manifest)
+}
+
+/// The absolute paths of the files in `root_path`, including config files, which determine the
+/// output of [`EmbeddedMiniCdn::new_compressed_with_options`].
+#[doc(hidden)]
+#[cfg(feature = "walkdir")]
+pub fn source_paths(root_path: &str) -> impl Iterator<Item = String> + '_ {
+    get_paths(root_path).map(|(absolute_path, _)| absolute_path)
+}
+
 #[cfg(feature = "walkdir")]
 fn get_paths(root_path: &str) -> impl Iterator<Item = (String, String)> + '_ {
     walkdir::WalkDir::new(&root_path)
@@ -503,14 +1525,35 @@ fn get_paths(root_path: &str) -> impl Iterator<Item = (String, String)> + '_ {
         })
 }
 
-#[cfg(all(any(feature = "mime", feature = "webp"), feature = "walkdir"))]
+#[cfg(all(
+    any(
+        feature = "mime",
+        feature = "webp",
+        feature = "rewrite",
+        feature = "brotli",
+        feature = "gzip",
+        feature = "minify_html",
+        feature = "minify_css",
+        feature = "minify_js",
+        feature = "minify_json",
+        feature = "minify_svg",
+        feature = "transform",
+        feature = "encoder"
+    ),
+    feature = "walkdir"
+))]
 fn mime(path: &str) -> String {
+    // Including generated source maps, which `mime_guess` doesn't recognize.
+    #[cfg(feature = "minify_js")]
+    if path.ends_with(".map") {
+        return "application/json".to_owned();
+    }
     mime_guess::from_path(&path)
         .first_or_octet_stream()
         .to_string()
 }
 
-#[cfg(feature = "last_modified")]
+#[cfg(all(feature = "last_modified", feature = "walkdir"))]
 fn last_modified(absolute_path: &str) -> String {
     use std::time::SystemTime;
     std::fs::metadata(absolute_path)
@@ -532,7 +1575,17 @@ fn last_modified(absolute_path: &str) -> String {
         .to_string()
 }
 
-#[cfg(feature = "etag")]
+#[cfg(all(feature = "config", feature = "walkdir"))]
+fn headers(
+    headers: std::collections::BTreeMap<String, String>,
+) -> Cow<'static, [(Cow<'static, str>, Cow<'static, str>)]> {
+    headers
+        .into_iter()
+        .map(|(name, value)| (Cow::Owned(name), Cow::Owned(value)))
+        .collect()
+}
+
+#[cfg(all(feature = "etag", feature = "walkdir"))]
 fn etag(contents: &[u8]) -> String {
     let mut etag = sha256::digest(contents);
     etag.truncate(32);
@@ -540,39 +1593,68 @@ fn etag(contents: &[u8]) -> String {
     etag
 }
 
-#[cfg(feature = "brotli")]
-fn brotli(contents: &[u8], buffer_size: usize, quality: u8, lgwin: u8) -> Option<Vec<u8>> {
+/// Inserts a content hash before the extension of the file name, e.g. `app.3f2a9c1e.js`.
+#[cfg(all(feature = "fingerprint", feature = "walkdir"))]
+fn fingerprint(path: &str, etag: &str) -> String {
+    let hash = &etag[..8];
+    let file_name_start = path.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match path[file_name_start..].rfind('.') {
+        Some(i) if i > 0 => {
+            let (stem, extension) = path.split_at(file_name_start + i);
+            format!("{stem}.{hash}{extension}")
+        }
+        _ => format!("{path}.{hash}"),
+    }
+}
+
+/// Whether `output` is small enough compared to the original, given the
+/// [`CompressOptions::compression_threshold`].
+#[cfg(all(
+    any(
+        feature = "brotli",
+        feature = "gzip",
+        feature = "webp",
+        feature = "encoder"
+    ),
+    feature = "walkdir"
+))]
+fn worthwhile(output: &[u8], original: &[u8], threshold: f32) -> bool {
+    (output.len() as f64) < original.len() as f64 * threshold as f64
+}
+
+#[cfg(all(feature = "brotli", feature = "walkdir"))]
+fn brotli(contents: &[u8], buffer_size: usize, quality: u8, lgwin: u8) -> Vec<u8> {
     use std::io::Write;
     let mut output = Vec::new();
     let mut writer =
         brotli::CompressorWriter::new(&mut output, buffer_size, quality as u32, lgwin as u32);
     writer.write_all(contents).unwrap();
     drop(writer);
-    if output.len() * 10 / 9 < contents.len() {
-        Some(output)
-    } else {
-        // Compression is counterproductive.
-        None
-    }
+    output
 }
 
-#[cfg(feature = "gzip")]
-fn gzip(contents: &[u8], level: u8) -> Option<Vec<u8>> {
+#[cfg(all(feature = "gzip", any(feature = "walkdir", feature = "lazy")))]
+fn gzip(contents: &[u8], level: u8) -> Vec<u8> {
     use flate2::write::GzEncoder;
     use flate2::Compression;
     use std::io::Write;
     let mut encoder = GzEncoder::new(Vec::new(), Compression::new(level as u32));
     encoder.write_all(contents.as_ref()).unwrap();
-    let vec = encoder.finish().unwrap();
-    if vec.len() * 10 / 9 < contents.len() {
-        Some(vec)
-    } else {
-        // Compression is counterproductive.
-        None
-    }
+    encoder.finish().unwrap()
 }
 
-#[cfg(feature = "webp")]
+#[cfg(all(feature = "zopfli", feature = "walkdir"))]
+fn zopfli(contents: &[u8], iterations: u64) -> Vec<u8> {
+    let options = zopfli::Options {
+        iteration_count: std::num::NonZeroU64::new(iterations.max(1)).unwrap(),
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    zopfli::compress(options, zopfli::Format::Gzip, contents, &mut output).unwrap();
+    output
+}
+
+#[cfg(all(feature = "webp", feature = "walkdir"))]
 fn webp(contents: &[u8], mime_essence: &str, quality: Option<f32>) -> Option<Vec<u8>> {
     use std::io::Cursor;
     let cursor = Cursor::new(contents);
@@ -593,13 +1675,8 @@ fn webp(contents: &[u8], mime_essence: &str, quality: Option<f32>) -> Option<Vec
                 encoder.encode_lossless()
             };
 
-            if webp_image.len() * 10 / 9 < contents.len() {
-                // Compression is counterproductive.
-                use std::ops::Deref;
-                Some(webp_image.deref().to_vec())
-            } else {
-                None
-            }
+            use std::ops::Deref;
+            Some(webp_image.deref().to_vec())
         }
         Err(_) => None,
     }
@@ -0,0 +1,158 @@
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::collections::BTreeMap;
+
+/// Used if the root config doesn't specify `markdown_layout`.
+const DEFAULT_LAYOUT: &str = "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{{ title }}</title>
+</head>
+<body>
+{{ content }}
+</body>
+</html>
+";
+
+/// Whether a file is Markdown, based on its extension.
+pub(crate) fn is_markdown(relative_path: &str) -> bool {
+    relative_path.ends_with(".md") || relative_path.ends_with(".markdown")
+}
+
+/// The path a Markdown file is served at once rendered.
+pub(crate) fn html_path(relative_path: &str) -> String {
+    let (stem, _) = relative_path.rsplit_once('.').unwrap();
+    format!("{}.html", stem)
+}
+
+/// Renders a Markdown document, which may begin with TOML front matter between `+++` lines,
+/// into `layout`. The layout's `{{ content }}` is replaced with the rendered HTML, and other
+/// `{{ key }}` placeholders with the (escaped) front matter values, or nothing. Fails if the
+/// document isn't UTF-8 or the front matter isn't valid, including if it is YAML (between `---`
+/// lines), which isn't supported.
+pub(crate) fn render(
+    relative_path: &str,
+    contents: &[u8],
+    layout: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let contents = std::str::from_utf8(contents)
+        .map_err(|e| format!("{}: invalid UTF-8: {}", relative_path, e))?;
+    if split_block(contents, "---").is_some() {
+        return Err(format!(
+            "{}: YAML front matter isn't supported, use TOML between `+++` lines",
+            relative_path
+        ));
+    }
+    let (front_matter, markdown) = match split_block(contents, "+++") {
+        Some((front_matter, markdown)) => (Some(front_matter), markdown),
+        None => (None, contents),
+    };
+    let mut variables = match front_matter {
+        Some(front_matter) => toml::from_str::<BTreeMap<String, toml::Value>>(front_matter)
+            .map_err(|e| format!("{}: invalid front matter: {}", relative_path, e))?
+            .into_iter()
+            .filter_map(|(key, value)| {
+                let value = match value {
+                    toml::Value::Boolean(b) => b.to_string(),
+                    toml::Value::Integer(n) => n.to_string(),
+                    toml::Value::Float(n) => n.to_string(),
+                    toml::Value::Datetime(d) => d.to_string(),
+                    toml::Value::String(s) => s,
+                    _ => return None,
+                };
+                Some((key, escape(&value)))
+            })
+            .collect(),
+        None => BTreeMap::new(),
+    };
+
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    // Links to other Markdown files should point to where they are served.
+    let events = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if is_local(&dest_url) => {
+            let (path, fragment) = match dest_url.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment)),
+                None => (&*dest_url, None),
+            };
+            let dest_url = if is_markdown(path) {
+                let mut rewritten = html_path(path);
+                if let Some(fragment) = fragment {
+                    rewritten.push('#');
+                    rewritten.push_str(fragment);
+                }
+                rewritten.into()
+            } else {
+                dest_url
+            };
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            })
+        }
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    variables.insert("content".to_owned(), html);
+
+    let mut layout = layout.unwrap_or(DEFAULT_LAYOUT);
+    let mut ret = String::with_capacity(layout.len() + variables["content"].len());
+    while let Some((before, after)) = layout.split_once("{{") {
+        let Some((key, after)) = after.split_once("}}") else {
+            break;
+        };
+        ret.push_str(before);
+        if let Some(value) = variables.get(key.trim()) {
+            ret.push_str(value);
+        }
+        layout = after;
+    }
+    ret.push_str(layout);
+    Ok(ret.into_bytes())
+}
+
+/// Splits off front matter between lines of `delimiter` at the start, if present.
+fn split_block<'a>(contents: &'a str, delimiter: &str) -> Option<(&'a str, &'a str)> {
+    let rest = contents.strip_prefix(delimiter)?;
+    let rest = rest
+        .strip_prefix('\n')
+        .or_else(|| rest.strip_prefix("\r\n"))?;
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((&rest[..offset], &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Whether a link refers to a file in the same tree, as opposed to another site.
+fn is_local(url: &str) -> bool {
+    !url.contains(':') && !url.starts_with("//")
+}
+
+fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&#39;"),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
@@ -0,0 +1,436 @@
+#[cfg(feature = "minify_css")]
+use crate::config::CssTargets;
+use crate::config::{Config, RootConfig};
+
+/// Minifies `contents`, if its MIME type is supported. Otherwise, returns it unchanged. Also
+/// returns a source map, for JS files with `source_map = true`.
+#[allow(unused_variables)]
+pub(crate) fn minify(
+    absolute_path: &str,
+    relative_path: &str,
+    mime: &str,
+    contents: Vec<u8>,
+    config: &Config,
+    root_config: &RootConfig,
+) -> (Vec<u8>, Option<Vec<u8>>) {
+    let minified = match mime {
+        #[cfg(feature = "minify_html")]
+        "text/html" => html(&contents),
+        #[cfg(feature = "minify_css")]
+        "text/css" => css(absolute_path, &contents, root_config),
+        #[cfg(feature = "minify_js")]
+        mime if is_js(mime) => return js(relative_path, &contents, config),
+        #[cfg(feature = "minify_json")]
+        "application/json" => json(relative_path, &contents),
+        #[cfg(feature = "minify_svg")]
+        "image/svg+xml" => svg(relative_path, &contents),
+        _ => contents,
+    };
+    (minified, None)
+}
+
+/// Minifies an HTML document, including inline CSS and JS.
+#[cfg(feature = "minify_html")]
+fn html(contents: &[u8]) -> Vec<u8> {
+    let cfg = minify_html::Cfg {
+        // Keep the output valid HTML.
+        do_not_minify_doctype: true,
+        ensure_spec_compliant_unquoted_attribute_values: true,
+        keep_spaces_between_attributes: true,
+        minify_css: true,
+        minify_js: true,
+        ..minify_html::Cfg::default()
+    };
+    minify_html::minify(contents, &cfg)
+}
+
+/// Minifies a stylesheet, inlining `@import`s of local files and lowering syntax that
+/// [`RootConfig::css_targets`] don't support.
+///
+/// `url()`s in imported files are not adjusted, so they should be in the same directory as
+/// the importing file.
+#[cfg(feature = "minify_css")]
+fn css(absolute_path: &str, contents: &[u8], root_config: &RootConfig) -> Vec<u8> {
+    use lightningcss::bundler::Bundler;
+    use lightningcss::printer::PrinterOptions;
+    use lightningcss::stylesheet::{MinifyOptions, ParserOptions};
+    use lightningcss::targets::Targets;
+
+    let targets = Targets::from(css_browsers(root_config));
+    let provider = LocalFileProvider {
+        files: lightningcss::bundler::FileProvider::new(),
+        entry: (
+            std::path::PathBuf::from(absolute_path),
+            String::from_utf8_lossy(contents).into_owned(),
+        ),
+    };
+    let mut bundler = Bundler::new(&provider, None, ParserOptions::default());
+    let mut stylesheet = bundler
+        .bundle(std::path::Path::new(absolute_path))
+        .unwrap_or_else(|e| panic!("failed to parse CSS: {}", e));
+    stylesheet
+        .minify(MinifyOptions {
+            targets,
+            ..MinifyOptions::default()
+        })
+        .unwrap_or_else(|e| panic!("failed to minify CSS: {}", e));
+    stylesheet
+        .to_css(PrinterOptions {
+            minify: true,
+            targets,
+            ..PrinterOptions::default()
+        })
+        .unwrap_or_else(|e| panic!("failed to print CSS: {}", e))
+        .code
+        .into_bytes()
+}
+
+/// Reads imported files relative to the importing file, leaving URLs (including root-relative
+/// ones) to the browser. The file being minified is read from memory, since it may have been
+/// transformed.
+#[cfg(feature = "minify_css")]
+struct LocalFileProvider {
+    files: lightningcss::bundler::FileProvider,
+    entry: (std::path::PathBuf, String),
+}
+
+#[cfg(feature = "minify_css")]
+impl lightningcss::bundler::SourceProvider for LocalFileProvider {
+    type Error = std::io::Error;
+
+    fn read<'a>(&'a self, file: &std::path::Path) -> Result<&'a str, Self::Error> {
+        if file == self.entry.0 {
+            Ok(&self.entry.1)
+        } else {
+            self.files.read(file)
+        }
+    }
+
+    fn resolve(
+        &self,
+        specifier: &str,
+        originating_file: &std::path::Path,
+    ) -> Result<lightningcss::bundler::ResolveResult, Self::Error> {
+        use lightningcss::bundler::ResolveResult;
+        if specifier.starts_with('/') || specifier.contains(':') {
+            Ok(ResolveResult::External(specifier.to_owned()))
+        } else {
+            self.files.resolve(specifier, originating_file)
+        }
+    }
+}
+
+/// Converts [`RootConfig::css_targets`] to the form `lightningcss` expects.
+#[cfg(feature = "minify_css")]
+fn css_browsers(root_config: &RootConfig) -> Option<lightningcss::targets::Browsers> {
+    let versions = match &root_config.css_targets {
+        CssTargets::Versions(versions) if versions.is_empty() => return None,
+        CssTargets::Versions(versions) => versions,
+        CssTargets::Query(queries) => {
+            return lightningcss::targets::Browsers::from_browserslist(queries).unwrap_or_else(
+                |e| {
+                    panic!(
+                        "invalid browserslist query in css_targets {:?}: {}",
+                        queries, e
+                    )
+                },
+            )
+        }
+    };
+    let mut browsers = lightningcss::targets::Browsers::default();
+    for (browser, version) in versions {
+        let field = match browser.as_str() {
+            "android" => &mut browsers.android,
+            "chrome" => &mut browsers.chrome,
+            "edge" => &mut browsers.edge,
+            "firefox" => &mut browsers.firefox,
+            "ie" => &mut browsers.ie,
+            "ios_saf" => &mut browsers.ios_saf,
+            "opera" => &mut browsers.opera,
+            "safari" => &mut browsers.safari,
+            "samsung" => &mut browsers.samsung,
+            _ => panic!("unknown browser in css_targets: {:?}", browser),
+        };
+        *field = Some(parse_version(version).unwrap_or_else(|| {
+            panic!(
+                "invalid version in css_targets: {} = {:?}",
+                browser, version
+            )
+        }));
+    }
+    Some(browsers)
+}
+
+/// Parses `major[.minor[.patch]]`, packed one byte per part like `lightningcss` does.
+#[cfg(feature = "minify_css")]
+fn parse_version(version: &str) -> Option<u32> {
+    let mut packed = 0;
+    let mut parts = version.split('.');
+    for shift in [16, 8, 0] {
+        let part = parts.next().map_or(Ok(0), str::parse::<u8>).ok()?;
+        packed |= u32::from(part) << shift;
+    }
+    parts.next().is_none().then_some(packed)
+}
+
+/// Whether `mime` is a JavaScript MIME type.
+#[cfg(feature = "minify_js")]
+pub(crate) fn is_js(mime: &str) -> bool {
+    matches!(mime, "application/javascript" | "text/javascript")
+}
+
+/// Minifies a script, or module if `relative_path` ends in `.mjs` or it fails to parse as a
+/// script. Scripts are minified less aggressively, since their top-level names are global.
+///
+/// If `config.source_map`, also returns a source map (including the original source), which
+/// the minified file links to at the same path plus `.map`.
+#[cfg(feature = "minify_js")]
+fn js(relative_path: &str, contents: &[u8], config: &Config) -> (Vec<u8>, Option<Vec<u8>>) {
+    use oxc_allocator::Allocator;
+    use oxc_codegen::{Codegen, CodegenOptions, CommentOptions, LegalComment};
+    use oxc_minifier::{Minifier, MinifierOptions};
+    use oxc_parser::Parser;
+    use oxc_span::SourceType;
+
+    let source = String::from_utf8_lossy(contents);
+    let allocator = Allocator::default();
+    let parse = |source_type| Parser::new(&allocator, &source, source_type).parse();
+    let mut parsed = parse(SourceType::script());
+    if relative_path.ends_with(".mjs") || parsed.panicked || parsed.diagnostics.has_errors() {
+        parsed = parse(SourceType::mjs());
+    }
+    if let Some(error) = parsed.diagnostics.errors().next() {
+        panic!("failed to minify {}: {}", relative_path, error);
+    }
+    let mut program = parsed.program;
+    let minified = Minifier::new(MinifierOptions::default()).minify(&allocator, &mut program);
+
+    // The map is served next to the file, so refers to it by name.
+    let file_name = &relative_path[relative_path.rfind('/').map_or(0, |i| i + 1)..];
+    let legal = if config.keep_license_comments {
+        LegalComment::Inline
+    } else {
+        LegalComment::None
+    };
+    let generated = Codegen::new()
+        .with_options(CodegenOptions {
+            comments: CommentOptions {
+                legal,
+                ..CommentOptions::disabled()
+            },
+            source_map_path: config.source_map.then(|| file_name.into()),
+            ..CodegenOptions::minify()
+        })
+        .with_scoping(minified.scoping)
+        .build(&program);
+
+    let mut output = generated.code;
+    let source_map = generated.map.map(|map| {
+        output.push_str(&format!("\n//# sourceMappingURL={}.map\n", file_name));
+        map.to_json_string().into_bytes()
+    });
+    (output.into_bytes(), source_map)
+}
+
+/// Removes whitespace from a JSON document, after checking that it is valid. Unlike
+/// reserializing, this keeps the order of keys and the exact form of numbers.
+#[cfg(feature = "minify_json")]
+fn json(relative_path: &str, contents: &[u8]) -> Vec<u8> {
+    serde_json::from_slice::<serde::de::IgnoredAny>(contents)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", relative_path, e));
+    let mut output = Vec::with_capacity(contents.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in contents {
+        if in_string {
+            output.push(byte);
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+        } else if !matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+            output.push(byte);
+            in_string = byte == b'"';
+        }
+    }
+    output
+}
+
+/// Namespaces of vector graphics editors, which are only meaningful to the editor.
+#[cfg(feature = "minify_svg")]
+const EDITOR_NAMESPACES: &[&str] = &[
+    "http://www.inkscape.org/namespaces/inkscape",
+    "http://sodipodi.sourceforge.net/DTD/sodipodi-0.dtd",
+    "http://www.bohemiancoding.com/sketch/ns",
+    "http://ns.adobe.com/AdobeIllustrator/10.0/",
+    "http://ns.adobe.com/AdobeSVGViewerExtensions/3.0/",
+    "http://ns.adobe.com/Graphs/1.0/",
+    "http://ns.adobe.com/Variables/1.0/",
+    "http://ns.adobe.com/SaveForWeb/1.0/",
+    "http://ns.adobe.com/Extensibility/1.0/",
+    "http://ns.adobe.com/Flows/1.0/",
+    "http://ns.adobe.com/ImageReplacement/1.0/",
+    "http://ns.adobe.com/GenericCustomNamespace/1.0/",
+    "http://ns.adobe.com/XPath/1.0/",
+    "http://www.serif.com/",
+];
+
+/// An element being copied by [`svg`].
+#[cfg(feature = "minify_svg")]
+#[derive(Default)]
+struct SvgElement<'a> {
+    /// Whether to leave out the element and its children.
+    skip: bool,
+    /// Whether to copy text verbatim.
+    preserve: bool,
+    /// Whether whitespace between children is significant (e.g. between `<tspan>`s).
+    text: bool,
+    /// Namespace prefixes declared on the element, and whether they belong to an editor.
+    prefixes: Vec<(&'a str, bool)>,
+}
+
+/// Whether `prefix` is bound to an editor namespace, by `element` or the nearest ancestor that
+/// declares it.
+#[cfg(feature = "minify_svg")]
+fn is_editor_prefix(ancestors: &[SvgElement<'_>], element: &SvgElement<'_>, prefix: &str) -> bool {
+    std::iter::once(element)
+        .chain(ancestors.iter().rev())
+        .find_map(|element| {
+            element
+                .prefixes
+                .iter()
+                .find(|(declared, _)| *declared == prefix)
+        })
+        .is_some_and(|&(_, editor)| editor)
+}
+
+/// Removes comments, metadata, editor namespaces and insignificant whitespace from an SVG
+/// image.
+#[cfg(feature = "minify_svg")]
+fn svg(relative_path: &str, contents: &[u8]) -> Vec<u8> {
+    use xmlparser::{ElementEnd, Token, Tokenizer};
+
+    let source = std::str::from_utf8(contents)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", relative_path, e));
+    let mut output = String::with_capacity(source.len());
+    let mut ancestors = vec![SvgElement::default()];
+    // The start tag being read, and the element it opens. Editor prefixes may be used before
+    // they are declared (Inkscape declares them last), so attributes are only copied once all
+    // of them have been read.
+    let mut tag = "";
+    let mut attributes = Vec::<(&str, &str)>::new();
+    let mut element = None::<(&str, &str, SvgElement)>;
+
+    for token in Tokenizer::from(source) {
+        let token = token.unwrap_or_else(|e| panic!("failed to parse {}: {}", relative_path, e));
+        let parent = ancestors.last().unwrap();
+        match token {
+            Token::Declaration { .. }
+            | Token::ProcessingInstruction { .. }
+            | Token::Comment { .. } => {}
+            Token::DtdStart { span, .. }
+            | Token::EmptyDtd { span, .. }
+            | Token::EntityDeclaration { span, .. }
+            | Token::DtdEnd { span } => output.push_str(&span),
+            Token::ElementStart {
+                prefix,
+                local,
+                span,
+            } => {
+                tag = span.as_str();
+                attributes.clear();
+                element = Some((
+                    prefix.as_str(),
+                    local.as_str(),
+                    SvgElement {
+                        skip: parent.skip,
+                        preserve: parent.preserve || matches!(&*local, "script" | "style"),
+                        text: matches!(&*local, "text" | "tspan" | "textPath"),
+                        prefixes: Vec::new(),
+                    },
+                ));
+            }
+            Token::Attribute {
+                prefix,
+                local,
+                value,
+                span,
+            } => {
+                let (_, _, element) = element.as_mut().unwrap();
+                if &*prefix == "xmlns" {
+                    element
+                        .prefixes
+                        .push((local.as_str(), EDITOR_NAMESPACES.contains(&&*value)));
+                } else if &*prefix == "xml" && &*local == "space" {
+                    element.preserve = &*value == "preserve";
+                }
+                // A declaration is dropped along with the editor attributes that use it.
+                let uses = if &*prefix == "xmlns" { &local } else { &prefix };
+                attributes.push((uses.as_str(), span.as_str()));
+            }
+            Token::ElementEnd { end, .. } => match end {
+                ElementEnd::Open | ElementEnd::Empty => {
+                    let (prefix, local, mut element) = element.take().unwrap();
+                    element.skip |= is_editor_prefix(&ancestors, &element, prefix)
+                        || (prefix.is_empty() && local == "metadata");
+                    if !element.skip {
+                        output.push_str(tag);
+                        for &(prefix, span) in &attributes {
+                            if !is_editor_prefix(&ancestors, &element, prefix) {
+                                output.push(' ');
+                                output.push_str(span);
+                            }
+                        }
+                        output.push_str(if let ElementEnd::Open = end {
+                            ">"
+                        } else {
+                            "/>"
+                        });
+                    }
+                    if let ElementEnd::Open = end {
+                        ancestors.push(element);
+                    }
+                }
+                ElementEnd::Close(prefix, local) => {
+                    if !ancestors.pop().unwrap().skip {
+                        output.push_str("</");
+                        if !prefix.is_empty() {
+                            output.push_str(&prefix);
+                            output.push(':');
+                        }
+                        output.push_str(&local);
+                        output.push('>');
+                    }
+                }
+            },
+            Token::Text { .. } | Token::Cdata { .. } if parent.skip => {}
+            Token::Text { text } => {
+                if parent.preserve {
+                    output.push_str(&text);
+                } else {
+                    let collapsed = text.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                    let before = text.starts_with(|c: char| c.is_ascii_whitespace());
+                    let after = text.ends_with(|c: char| c.is_ascii_whitespace());
+                    if collapsed.is_empty() {
+                        if parent.text && (before || after) {
+                            output.push(' ');
+                        }
+                    } else {
+                        if before {
+                            output.push(' ');
+                        }
+                        output.push_str(&collapsed);
+                        if after {
+                            output.push(' ');
+                        }
+                    }
+                }
+            }
+            Token::Cdata { span, .. } => output.push_str(&span),
+        }
+    }
+    output.into_bytes()
+}
@@ -0,0 +1,289 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// Whether references inside files of this MIME type can be rewritten.
+pub(crate) fn is_rewritable(mime: &str) -> bool {
+    matches!(mime, "text/html" | "text/css")
+}
+
+/// Rewrites local references inside an HTML or CSS file to point to fingerprinted files.
+///
+/// `manifest` maps paths (relative to the root) to fingerprinted paths.
+pub(crate) fn rewrite(
+    relative_path: &str,
+    mime: &str,
+    contents: &[u8],
+    manifest: &HashMap<String, String>,
+) -> Vec<u8> {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return contents.to_vec();
+    };
+    let directory = &relative_path[..relative_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+    let reference = |reference: &str| {
+        rewrite_reference(directory, reference, &|resolved| {
+            manifest.get(resolved).cloned()
+        })
+    };
+    match mime {
+        "text/html" => rewrite_html(text, &reference),
+        "text/css" => rewrite_css(text, &reference),
+        _ => text.to_owned(),
+    }
+    .into_bytes()
+}
+
+/// The paths in `paths` that an HTML or CSS file references.
+pub(crate) fn references(
+    relative_path: &str,
+    mime: &str,
+    contents: &[u8],
+    paths: &HashSet<String>,
+) -> HashSet<String> {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return HashSet::new();
+    };
+    let directory = &relative_path[..relative_path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+    let found = RefCell::new(HashSet::new());
+    let reference = |reference: &str| {
+        rewrite_reference(directory, reference, &|resolved| {
+            if paths.contains(resolved) {
+                found.borrow_mut().insert(resolved.to_owned());
+            }
+            None
+        })
+    };
+    match mime {
+        "text/html" => rewrite_html(text, &reference),
+        "text/css" => rewrite_css(text, &reference),
+        _ => String::new(),
+    };
+    found.into_inner()
+}
+
+/// Whether `path` leads back to itself by following `references`, in which case its
+/// fingerprint would depend on itself.
+pub(crate) fn is_cyclic(path: &str, references: &HashMap<String, HashSet<String>>) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![path];
+    while let Some(current) = stack.pop() {
+        for next in references.get(current).into_iter().flatten() {
+            if next == path {
+                return true;
+            }
+            if visited.insert(next.as_str()) {
+                stack.push(next);
+            }
+        }
+    }
+    false
+}
+
+/// Rewrites `href`, `src`, `srcset`, `poster` and `style` attributes, as well as `<style>`
+/// elements.
+fn rewrite_html(html: &str, reference: &dyn Fn(&str) -> Option<String>) -> String {
+    use lol_html::html_content::ContentType;
+    use lol_html::{element, rewrite_str, text, RewriteStrSettings};
+
+    let style = RefCell::new(String::new());
+
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings::new()
+            .append_element_content_handler(element!("*", |element| {
+                for name in ["href", "src", "poster"] {
+                    if let Some(rewritten) = element
+                        .get_attribute(name)
+                        .and_then(|value| reference(&value))
+                    {
+                        element.set_attribute(name, &rewritten)?;
+                    }
+                }
+                if let Some(srcset) = element.get_attribute("srcset") {
+                    let rewritten = rewrite_srcset(&srcset, reference);
+                    if rewritten != srcset {
+                        element.set_attribute("srcset", &rewritten)?;
+                    }
+                }
+                if let Some(style) = element.get_attribute("style") {
+                    let rewritten = rewrite_css(&style, reference);
+                    if rewritten != style {
+                        element.set_attribute("style", &rewritten)?;
+                    }
+                }
+                Ok(())
+            }))
+            .append_element_content_handler(text!("style", |chunk| {
+                // The contents may arrive in multiple chunks.
+                let mut style = style.borrow_mut();
+                style.push_str(chunk.as_str());
+                if chunk.last_in_text_node() {
+                    chunk.replace(&rewrite_css(&style, reference), ContentType::Html);
+                    style.clear();
+                } else {
+                    chunk.remove();
+                }
+                Ok(())
+            })),
+    );
+
+    result.unwrap_or_else(|_| html.to_owned())
+}
+
+/// Rewrites the URLs of a `srcset` attribute, e.g. `a.png 1x, b.png 2x`.
+fn rewrite_srcset(srcset: &str, reference: &dyn Fn(&str) -> Option<String>) -> String {
+    srcset
+        .split(',')
+        .map(|candidate| {
+            let candidate = candidate.trim();
+            let (url, descriptor) = candidate
+                .split_once(char::is_whitespace)
+                .unwrap_or((candidate, ""));
+            match reference(url) {
+                Some(rewritten) if descriptor.is_empty() => rewritten,
+                Some(rewritten) => format!("{} {}", rewritten, descriptor.trim()),
+                None => candidate.to_owned(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Rewrites `url(...)` and `@import "..."`, skipping comments.
+fn rewrite_css(css: &str, reference: &dyn Fn(&str) -> Option<String>) -> String {
+    let bytes = css.as_bytes();
+    let mut output = String::with_capacity(css.len());
+    // Everything before this has been copied to the output.
+    let mut copied = 0;
+    let mut i = 0;
+
+    // Copies up to `start`, then the rewritten reference in place of `start..end`.
+    let splice = |output: &mut String, copied: &mut usize, start: usize, end: usize| {
+        if let Some(rewritten) = reference(&css[start..end]) {
+            output.push_str(&css[*copied..start]);
+            output.push_str(&rewritten);
+            *copied = end;
+        }
+    };
+
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if rest.starts_with(b"/*") {
+            i = css[i + 2..]
+                .find("*/")
+                .map(|j| i + 2 + j + 2)
+                .unwrap_or(bytes.len());
+        } else if rest.len() >= 4
+            && rest[..4].eq_ignore_ascii_case(b"url(")
+            && (i == 0 || !is_identifier(bytes[i - 1]))
+        {
+            i += 4;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            i = if let Some(&quote @ (b'"' | b'\'')) = bytes.get(i) {
+                let (start, end) = string_bounds(css, i, quote);
+                splice(&mut output, &mut copied, start, end);
+                end + 1
+            } else {
+                let close = css[i..].find(')').map(|j| i + j).unwrap_or(bytes.len());
+                splice(
+                    &mut output,
+                    &mut copied,
+                    i,
+                    i + css[i..close].trim_end().len(),
+                );
+                close
+            };
+        } else if rest.len() >= 7 && rest[..7].eq_ignore_ascii_case(b"@import") {
+            i += 7;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if let Some(&quote @ (b'"' | b'\'')) = bytes.get(i) {
+                let (start, end) = string_bounds(css, i, quote);
+                splice(&mut output, &mut copied, start, end);
+                i = end + 1;
+            }
+        } else if let &quote @ (b'"' | b'\'') = &rest[0] {
+            // Skip over other strings, which may contain things that look like references.
+            i = (string_bounds(css, i, quote).1 + 1).min(bytes.len());
+        } else {
+            i += 1;
+        }
+    }
+
+    output.push_str(&css[copied..]);
+    output
+}
+
+/// Given the index of an opening quote, returns the bounds of the string's contents.
+fn string_bounds(css: &str, open: usize, quote: u8) -> (usize, usize) {
+    let bytes = css.as_bytes();
+    let mut i = open + 1;
+    while i < bytes.len() && bytes[i] != quote {
+        if bytes[i] == b'\\' {
+            i += 1;
+        }
+        i += 1;
+    }
+    (open + 1, i.min(bytes.len()))
+}
+
+fn is_identifier(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'
+}
+
+/// If `reference` (found in a file in `directory`) points to a fingerprinted file, returns the
+/// reference with the file name replaced. `fingerprinted` maps resolved paths (relative to the
+/// root) to fingerprinted paths.
+fn rewrite_reference(
+    directory: &str,
+    reference: &str,
+    fingerprinted: &dyn Fn(&str) -> Option<String>,
+) -> Option<String> {
+    if reference.starts_with("//") || has_scheme(reference) {
+        // Not local.
+        return None;
+    }
+    let (path, suffix) = reference.split_at(reference.find(['?', '#']).unwrap_or(reference.len()));
+    if path.is_empty() {
+        return None;
+    }
+    let resolved = if let Some(absolute) = path.strip_prefix('/') {
+        normalize(absolute)?
+    } else {
+        normalize(&format!("{}{}", directory, path))?
+    };
+    let fingerprinted = fingerprinted(&resolved)?;
+    let file_name = &fingerprinted[fingerprinted.rfind('/').map(|i| i + 1).unwrap_or(0)..];
+    let path_directory = &path[..path.rfind('/').map(|i| i + 1).unwrap_or(0)];
+    Some(format!("{}{}{}", path_directory, file_name, suffix))
+}
+
+/// Whether the reference starts with something like `https:` or `data:`.
+fn has_scheme(reference: &str) -> bool {
+    match reference.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Resolves `.` and `..` segments, returning `None` if the path escapes the root.
+fn normalize(path: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
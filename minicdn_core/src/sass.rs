@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+/// Whether a file is a Sass entry point, to be compiled to CSS.
+pub(crate) fn is_scss(relative_path: &str) -> bool {
+    relative_path.ends_with(".scss")
+}
+
+/// Whether a file is a Sass partial (e.g. `_variables.scss`), which is only imported by other
+/// files and not served itself.
+pub(crate) fn is_partial(relative_path: &str) -> bool {
+    let file_name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+    file_name.starts_with('_') && is_scss(file_name)
+}
+
+/// The path an entry point is served at once compiled.
+pub(crate) fn css_path(relative_path: &str) -> String {
+    format!("{}.css", relative_path.strip_suffix(".scss").unwrap())
+}
+
+/// Compiles an entry point to CSS, resolving imports relative to `absolute_path`. On failure,
+/// returns the error with its file and line.
+pub(crate) fn compile(absolute_path: &str, contents: &[u8]) -> Result<Vec<u8>, String> {
+    let fs = EntryFs {
+        entry: (PathBuf::from(absolute_path), contents),
+    };
+    let options = grass::Options::default().fs(&fs);
+    grass::from_path(absolute_path, &options)
+        .map(String::into_bytes)
+        .map_err(|e| match e.kind() {
+            grass::ErrorKind::ParseError { message, loc, .. } => format!(
+                "{}:{}:{}: {}",
+                loc.file.name(),
+                loc.begin.line + 1,
+                loc.begin.column + 1,
+                message
+            ),
+            kind => format!("{}: {:?}", absolute_path, kind),
+        })
+}
+
+/// Reads the entry point from memory, as it may have been transformed, and imports from disk.
+#[derive(Debug)]
+struct EntryFs<'a> {
+    entry: (PathBuf, &'a [u8]),
+}
+
+impl grass::Fs for EntryFs<'_> {
+    fn is_dir(&self, path: &Path) -> bool {
+        grass::StdFs.is_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path == self.entry.0 || grass::StdFs.is_file(path)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        if path == self.entry.0 {
+            Ok(self.entry.1.to_vec())
+        } else {
+            grass::StdFs.read(path)
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        grass::StdFs.canonicalize(path)
+    }
+}
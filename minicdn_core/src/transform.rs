@@ -0,0 +1,192 @@
+/// Build-time processing of files, run by [`crate::EmbeddedMiniCdn::new_compressed_with_options`]
+/// (see [`crate::CompressOptions::transforms`]) before minification, fingerprinting and
+/// compression.
+///
+/// The `include_mini_cdn!` family of macros can't run Rust code from the crate using them, so
+/// they only support [`CommandTransform`]s (with the `transform` option). Register other
+/// transforms with `minicdn_build` to embed the output.
+///
+/// ```
+/// use minicdn_core::{Transform, TransformInput, TransformOutput};
+///
+/// /// Replaces `{{year}}` in HTML files.
+/// #[derive(Debug)]
+/// struct Year(u32);
+///
+/// impl Transform for Year {
+///     fn transform(&self, input: TransformInput<'_>) -> TransformOutput {
+///         if input.mime != "text/html" {
+///             return input.unchanged();
+///         }
+///         let contents = String::from_utf8_lossy(&input.contents)
+///             .replace("{{year}}", &self.0.to_string());
+///         TransformOutput {
+///             relative_path: input.relative_path.to_owned(),
+///             contents: contents.into_bytes(),
+///             extra_files: Vec::new(),
+///         }
+///     }
+/// }
+/// ```
+pub trait Transform: std::fmt::Debug + Send + Sync {
+    /// Processes a file, or returns it unchanged. Panics are reported as build errors.
+    fn transform(&self, input: TransformInput<'_>) -> TransformOutput;
+}
+
+/// A file to be transformed.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TransformInput<'a> {
+    /// The path of the file on disk, even if an earlier transform changed `relative_path`.
+    pub absolute_path: &'a str,
+    /// The path the file will be served at, relative to the root directory.
+    pub relative_path: &'a str,
+    /// The MIME type, based on `relative_path`.
+    pub mime: &'a str,
+    /// The contents, possibly altered by earlier transforms.
+    pub contents: Vec<u8>,
+    /// The file's config (see [`crate::CONFIG_SUFFIX`]), which may contain keys for transforms.
+    #[cfg(feature = "config")]
+    pub config: TransformConfig<'a>,
+}
+
+/// A file's config, for reading keys meant for a [`Transform`]. Keys that are missing or of
+/// another type read as `None`.
+#[cfg(feature = "config")]
+#[derive(Copy, Clone, Debug)]
+pub struct TransformConfig<'a>(pub(crate) &'a toml::value::Table);
+
+#[cfg(feature = "config")]
+impl TransformConfig<'_> {
+    /// Whether the key is present, with any type.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// A string, e.g. `banner = "Hello"`.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.0.get(key)?.as_str()
+    }
+
+    /// A boolean, e.g. `enabled = true`.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.0.get(key)?.as_bool()
+    }
+
+    /// An integer, e.g. `width = 640`. Floats aren't converted.
+    pub fn get_integer(&self, key: &str) -> Option<i64> {
+        self.0.get(key)?.as_integer()
+    }
+
+    /// Integers are converted, e.g. `ratio = 1` reads as `1.0`.
+    pub fn get_float(&self, key: &str) -> Option<f64> {
+        let value = self.0.get(key)?;
+        value
+            .as_float()
+            .or_else(|| value.as_integer().map(|n| n as f64))
+    }
+
+    /// A list of strings, or `None` if any element isn't a string.
+    pub fn get_str_list(&self, key: &str) -> Option<Vec<&str>> {
+        self.0
+            .get(key)?
+            .as_array()?
+            .iter()
+            .map(|v| v.as_str())
+            .collect()
+    }
+}
+
+impl TransformInput<'_> {
+    /// Leaves the file as it is.
+    pub fn unchanged(self) -> TransformOutput {
+        TransformOutput {
+            relative_path: self.relative_path.to_owned(),
+            contents: self.contents,
+            extra_files: Vec::new(),
+        }
+    }
+}
+
+/// The result of a [`Transform`].
+#[derive(Debug)]
+pub struct TransformOutput {
+    /// The path to serve the file at, e.g. to change its extension.
+    pub relative_path: String,
+    pub contents: Vec<u8>,
+    /// Additional (relative path, contents) pairs to serve. They are not transformed further
+    /// or fingerprinted, and otherwise share the file's config.
+    pub extra_files: Vec<(String, Vec<u8>)>,
+}
+
+/// Runs an external program on each file, e.g. a script, with the contents on its standard input.
+/// What it writes to its standard output replaces them. The path and MIME type are passed in the
+/// `MINICDN_RELATIVE_PATH`, `MINICDN_ABSOLUTE_PATH` and `MINICDN_MIME` environment variables and,
+/// with the `config` feature, the file's config (as TOML) in `MINICDN_CONFIG`.
+///
+/// The program can't rename files or generate extra ones. If it exits unsuccessfully, its standard
+/// error is reported as a build error.
+#[derive(Clone, Debug)]
+pub struct CommandTransform {
+    program: std::path::PathBuf,
+}
+
+impl CommandTransform {
+    /// Runs `program`, which is looked up like [`std::process::Command::new`] does.
+    pub fn new(program: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+        }
+    }
+}
+
+impl Transform for CommandTransform {
+    fn transform(&self, input: TransformInput<'_>) -> TransformOutput {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let mut command = Command::new(&self.program);
+        command
+            .env("MINICDN_RELATIVE_PATH", input.relative_path)
+            .env("MINICDN_ABSOLUTE_PATH", input.absolute_path)
+            .env("MINICDN_MIME", input.mime)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        #[cfg(feature = "config")]
+        {
+            // TOML requires values to come before tables, like `[headers]`.
+            let mut config = String::new();
+            toml::ser::tables_last(input.config.0, &mut toml::Serializer::new(&mut config))
+                .expect("failed to serialize config");
+            command.env("MINICDN_CONFIG", config);
+        }
+        let mut child = command
+            .spawn()
+            .unwrap_or_else(|e| panic!("failed to run {:?}: {}", self.program, e));
+        // Written from another thread, so a program that writes before reading all of its input
+        // can't deadlock.
+        let mut stdin = child.stdin.take().unwrap();
+        let contents = input.contents;
+        let writer = std::thread::spawn(move || stdin.write_all(&contents));
+        let output = child
+            .wait_with_output()
+            .unwrap_or_else(|e| panic!("failed to run {:?}: {}", self.program, e));
+        // The program may exit without reading everything.
+        let _ = writer.join();
+        if !output.status.success() {
+            panic!(
+                "{:?} failed on {} ({}): {}",
+                self.program,
+                input.relative_path,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+        }
+        TransformOutput {
+            relative_path: input.relative_path.to_owned(),
+            contents: output.stdout,
+            extra_files: Vec::new(),
+        }
+    }
+}
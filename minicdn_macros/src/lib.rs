@@ -1,14 +1,22 @@
-#![feature(proc_macro_span)]
+#![feature(proc_macro_diagnostic)]
 #![cfg_attr(feature = "track_path", feature(proc_macro_tracked_path))]
 
 extern crate core;
 
-use litrs::StringLit;
-use minicdn_core::EmbeddedMiniCdn;
-use proc_macro::TokenStream;
-use proc_macro2::{Literal, TokenTree};
-use quote::{quote, ToTokens, TokenStreamExt};
+use minicdn_build::{
+    embed_files, quote_embedded_mini_cdn, quote_manifest, reporting, Blobs, EmbeddedFile,
+};
+#[cfg(feature = "transform")]
+use minicdn_core::CommandTransform;
+use minicdn_core::{CompressOptions, Compressed, CompressedPath, EmbeddedMiniCdn};
+use proc_macro::{Diagnostic, Level, TokenStream};
+use quote::{quote, ToTokens};
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, Lit, LitStr, Token, Visibility};
 
 #[proc_macro]
 /// This macro evaluates the path relative to the source file.
@@ -20,9 +28,11 @@ use std::path::Path;
 /// # Debug mode
 ///
 /// References files so that they be loaded at runtime.
+///
+/// Options are passed through to [`include_mini_cdn!`] and ignored in debug mode.
 pub fn release_include_mini_cdn(args: TokenStream) -> TokenStream {
-    let arg = parse_arg(args);
-    let path = arg_to_path(&arg);
+    let forwarded = proc_macro2::TokenStream::from(args.clone());
+    let path = arg_to_path(&parse_args(args).path);
 
     quote! {
         {
@@ -33,7 +43,7 @@ pub fn release_include_mini_cdn(args: TokenStream) -> TokenStream {
 
             #[cfg(not(debug_assertions))]
             {
-                minicdn::MiniCdn::Embedded(minicdn::include_mini_cdn!(#arg))
+                minicdn::MiniCdn::Embedded(minicdn::include_mini_cdn!(#forwarded))
             }
         }
     }
@@ -44,118 +54,630 @@ pub fn release_include_mini_cdn(args: TokenStream) -> TokenStream {
 /// Compresses and embeds files at compile time (may incur significant compile time overhead).
 ///
 /// This macro evaluates the path relative to the source file.
+///
+/// # Options
+///
+/// Options may follow the path, e.g. `include_mini_cdn!("./public", fingerprint = true)`.
+///
+/// - `fingerprint` (requires the `fingerprint` feature): see [`CompressOptions::fingerprint`]
+/// - `fingerprint_keep_original` (requires the `fingerprint` feature): see
+///   [`CompressOptions::fingerprint_keep_original`]
+/// - `fingerprint_rewrite` (requires the `rewrite` feature): see
+///   [`CompressOptions::fingerprint_rewrite`]
+/// - `lazy` (requires the `lazy` feature): see [`CompressOptions::lazy`]
+/// - `compression_threshold`: see [`CompressOptions::compression_threshold`]
+/// - `transform` (requires the `transform` feature): runs a program on each file, see
+///   [`CommandTransform`]. Paths with a directory are relative to the source file, like the root
+///   path, and others are looked up in `PATH`. May be repeated to run several in order.
 pub fn include_mini_cdn(args: TokenStream) -> TokenStream {
-    let root_path = arg_to_path(&parse_arg(args));
+    let key = format!("include_mini_cdn!({})", args);
+    let args = parse_args(args);
+    let (root_path, compressed) = match compress(&args) {
+        Ok(compressed) => compressed,
+        Err(error) => return error.into(),
+    };
+    let mini_cdn = &compressed.mini_cdn;
 
-    let mut files = Vec::<proc_macro2::TokenStream>::new();
+    let key = format!("{} in {}", key, root_path);
+    quote_embedded_mini_cdn(&root_path, mini_cdn, &key, &mut track, &mut report).into()
+}
 
-    #[cfg(feature = "track_path")]
-    proc_macro::tracked::path(&root_path);
+#[proc_macro]
+/// Like [`include_mini_cdn!`], but generates a module with a `static` for each file, so files
+/// can be referenced without looking them up by path.
+///
+/// Directories become nested modules, and file names become upper case identifiers. For
+/// example, the following defines `assets::images::LOGO_PNG` of type `&'static MiniCdnFile`, as
+/// well as `assets::mini_cdn()`, which returns all the files as an `EmbeddedMiniCdn`, and
+/// `assets::asset("images/logo.png")`, which returns the path it is served at. Names that
+/// become the same identifier fail to compile.
+///
+/// ```ignore
+/// minicdn::include_mini_cdn_module!(pub mod assets, "./public", fingerprint = true);
+/// ```
+pub fn include_mini_cdn_module(args: TokenStream) -> TokenStream {
+    let key = format!("include_mini_cdn_module!({})", args);
+    let ModuleArgs {
+        visibility,
+        name,
+        args,
+    } = match syn::parse::<ModuleArgs>(args) {
+        Ok(args) => args,
+        Err(e) => panic!("error parsing arguments: {}", e),
+    };
+    let (root_path, compressed) = match compress(&args) {
+        Ok(compressed) => compressed,
+        Err(error) => return error.into(),
+    };
+    let mini_cdn = &compressed.mini_cdn;
 
-    #[allow(unused)]
-    EmbeddedMiniCdn::new_compressed(&root_path)
-        .iter()
-        .for_each(|(path, file)| {
-            #[cfg(feature = "track_path")]
-            proc_macro::tracked::path(&**path);
+    let mut blobs = Blobs::new(&format!("{} in {}", key, root_path));
+    let files = embed_files(&root_path, mini_cdn, &mut blobs, &mut track);
+    if let Some(message) = blobs.report(&root_path) {
+        report(&message);
+    }
 
-            #[allow(unused_mut)]
-            let mut fields = Vec::<proc_macro2::TokenStream>::new();
+    let mut module = Module::default();
+    let mut inserts = Vec::new();
+    for EmbeddedFile {
+        original,
+        paths,
+        file,
+    } in files
+    {
+        let mut segments = original.split('/').collect::<Vec<_>>();
+        let file_name = segments.pop().unwrap();
+
+        let mut parent = &mut module;
+        let mut modules = Vec::new();
+        for segment in segments {
+            let ident = identifier(segment, false);
+            if let Err(error) = parent.claim(&ident, segment) {
+                return error.into();
+            }
+            modules.push(ident.clone());
+            parent = parent.modules.entry(ident.to_string()).or_default();
+        }
 
-            #[allow(unused)]
-            use std::ops::Deref;
+        let ident = identifier(file_name, true);
+        if let Err(error) = parent.claim(&ident, file_name) {
+            return error.into();
+        }
+        parent.statics.insert(ident.to_string(), file);
 
-            #[cfg(feature = "etag")]
-            {
-                let etag = file.etag.deref();
-                fields.push(quote! {
-                    etag: #etag.into()
-                });
+        let static_path = quote! {
+            #(#modules::)*#ident
+        };
+        inserts.extend(paths.into_iter().map(|path| {
+            quote! {
+                ret.insert(std::borrow::Cow::Borrowed(#path), #static_path.clone());
             }
+        }));
+    }
+    let manifest = quote_manifest(mini_cdn);
+    let assets = asset_table(&compressed.paths)
+        .into_iter()
+        .map(|(path, served)| quote!((#path, #served)));
 
-            #[cfg(feature = "last_modified")]
-            {
-                let last_modified = file.last_modified.deref();
-                fields.push(quote! {
-                    last_modified: #last_modified.into()
-                });
+    quote! {
+        #visibility mod #name {
+            #blobs
+            #module
+
+            /// All files in this module.
+            pub fn mini_cdn() -> minicdn::EmbeddedMiniCdn {
+                let mut ret = minicdn::EmbeddedMiniCdn::default();
+                #(#inserts)*
+                #manifest
+                ret
             }
 
-            #[cfg(feature = "mime")]
-            {
-                let mime = file.mime.deref();
-                fields.push(quote! {
-                    mime: #mime.into()
-                });
+            /// The path a file is served at, which is fingerprinted if the file is. The file may
+            /// be given by its path before fingerprinting, or by its source if it was renamed
+            /// (e.g. `style.scss` for `style.css`). Panics if there is no such file, which fails
+            /// to compile if called in a `const`.
+            pub const fn asset(path: &str) -> &'static str {
+                const ASSETS: &[(&str, &str)] = &[#(#assets),*];
+                match minicdn::find_asset(ASSETS, path) {
+                    Some(served) => served,
+                    None => panic!("no such file in this module"),
+                }
             }
+        }
+    }
+    .into()
+}
 
-            #[cfg(feature = "brotli")]
-            {
-                let contents_brotli = quote_option_bytes(&file.contents_brotli);
-                fields.push(quote! {
-                    contents_brotli: #contents_brotli
-                });
+#[proc_macro]
+/// Like [`include_mini_cdn!`], but evaluates to a `&'static StaticMiniCdn`, a perfect hash map
+/// that requires no initialization at runtime.
+///
+/// ```ignore
+/// static FILES: &StaticMiniCdn = minicdn::include_mini_cdn_static!("./public");
+/// ```
+#[cfg(feature = "phf")]
+pub fn include_mini_cdn_static(args: TokenStream) -> TokenStream {
+    let key = format!("include_mini_cdn_static!({})", args);
+    let args = parse_args(args);
+    let (root_path, compressed) = match compress(&args) {
+        Ok(compressed) => compressed,
+        Err(error) => return error.into(),
+    };
+    let mini_cdn = &compressed.mini_cdn;
+
+    let mut files = Vec::new();
+    let mut entries = Vec::new();
+    let mut blobs = Blobs::new(&format!("{} in {}", key, root_path));
+    let embedded = embed_files(&root_path, mini_cdn, &mut blobs, &mut track);
+    if let Some(message) = blobs.report(&root_path) {
+        report(&message);
+    }
+
+    for (index, EmbeddedFile { paths, file, .. }) in embedded.into_iter().enumerate() {
+        files.push(file);
+        entries.extend(paths.into_iter().map(|path| {
+            (
+                path,
+                quote! {
+                    &FILES[#index]
+                },
+            )
+        }));
+    }
+    let count = files.len();
+    let (files_entries, files_map) =
+        quote_phf_map("FILE_ENTRIES", quote!(&minicdn::MiniCdnFile), entries);
+
+    let (manifest_entries, manifest_map) = quote_phf_map(
+        "MANIFEST_ENTRIES",
+        quote!(&str),
+        mini_cdn
+            .manifest()
+            .map(|(original, fingerprinted)| (original, quote!(#fingerprinted)))
+            .collect(),
+    );
+
+    quote! {
+        {
+            #blobs
+            static FILES: [minicdn::MiniCdnFile; #count] = [#(#files),*];
+            #files_entries
+            #manifest_entries
+            static MINI_CDN: minicdn::StaticMiniCdn =
+                minicdn::StaticMiniCdn::new(#files_map, #manifest_map);
+            &MINI_CDN
+        }
+    }
+    .into()
+}
+
+/// Compresses the files at the root path given by `args`, returning the root path (relative to
+/// the file system rather than the source file) and the result.
+fn compress(args: &Args) -> Result<(String, Compressed), proc_macro2::TokenStream> {
+    if let Some(file) = &args.file {
+        panic!("unexpected file path {:?}", file.value());
+    }
+    let root_path = arg_to_path(&args.path);
+    let options = compress_options(args);
+
+    track(&root_path);
+
+    let compressed = catch_panic(|| EmbeddedMiniCdn::compress(&root_path, &options))?;
+    compressed.report.iter().for_each(|message| report(message));
+    Ok((root_path, compressed))
+}
+
+/// Runs `f`, which compresses files, reporting a panic (e.g. a Sass syntax error) as a compile
+/// error instead of a crash of the macro.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, proc_macro2::TokenStream> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|payload| {
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("unknown error");
+        syn::Error::new(proc_macro2::Span::call_site(), message).to_compile_error()
+    })
+}
+
+/// A constant expression of type `phf::Map`, along with a `static` named `name` that holds its
+/// entries (which may reference other statics, so can't be a temporary).
+#[cfg(feature = "phf")]
+fn quote_phf_map(
+    name: &str,
+    value_type: proc_macro2::TokenStream,
+    entries: Vec<(&str, proc_macro2::TokenStream)>,
+) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let name = Ident::new(name, proc_macro2::Span::call_site());
+    let keys = entries.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+    let state = phf_generator::generate_hash(&keys);
+    let key = state.key;
+    let disps = state.disps.iter().map(|(d1, d2)| quote!((#d1, #d2)));
+    let count = entries.len();
+    let entries = state.map.iter().map(|&index| {
+        let (key, value) = &entries[index];
+        quote!((#key, #value))
+    });
+    (
+        quote! {
+            static #name: [(&str, #value_type); #count] = [#(#entries),*];
+        },
+        quote! {
+            minicdn::phf::Map {
+                key: #key,
+                disps: &[#(#disps),*],
+                entries: &#name,
             }
+        },
+    )
+}
 
-            #[cfg(feature = "gzip")]
+#[proc_macro]
+/// Evaluates to the path (relative to the root) that a file is served at by
+/// [`include_mini_cdn!`], failing to compile if the file doesn't exist.
+///
+/// Takes the same arguments as [`include_mini_cdn!`], with the path of the file after the root
+/// path, e.g. `asset!("./public", "images/logo.png", fingerprint = true)`. The result will be
+/// fingerprinted if the file would be. Renamed files can be given by either path, e.g.
+/// `style.scss` or `style.css`.
+///
+/// Nothing checks that the arguments match those of the macro that embeds the files, so prefer
+/// the `asset` function generated by [`include_mini_cdn_module!`] where possible.
+pub fn asset(args: TokenStream) -> TokenStream {
+    match asset_paths(args) {
+        Ok((_, served, track)) => quote! {
             {
-                let contents_gzip = quote_option_bytes(&file.contents_gzip);
-                fields.push(quote! {
-                    contents_gzip: #contents_gzip
-                });
+                #track
+                #served
             }
+        },
+        Err(error) => error,
+    }
+    .into()
+}
 
-            #[cfg(feature = "webp")]
+#[proc_macro]
+/// Like [`asset!`], but for use with [`release_include_mini_cdn!`].
+///
+/// # Release mode
+///
+/// Evaluates to the path that the file is served at (which may be fingerprinted).
+///
+/// # Debug mode
+///
+/// Evaluates to the path of the file before fingerprinting.
+pub fn release_asset(args: TokenStream) -> TokenStream {
+    match asset_paths(args) {
+        Ok((path, served, track)) => quote! {
             {
-                let contents_webp = quote_option_bytes(&file.contents_webp);
-                fields.push(quote! {
-                    contents_webp: #contents_webp
-                });
-            }
-
-            let include_path_raw = Path::new(&root_path).join(&**path);
-            let include_path_canonical = include_path_raw.canonicalize().expect(&format!(
-                "failed to canonicalize include path {:?}",
-                include_path_raw
-            ));
-            let include_path = include_path_canonical.to_str().expect(&format!(
-                "failed to stringify include path: {:?}",
-                include_path_canonical
-            ));
-
-            // Must use include_str! instead of file.contents so a change triggers a recompilation.
-            files.push(
-                quote! {
-                    ret.insert(std::borrow::Cow::Borrowed(#path), minicdn::MiniCdnFile{
-                        contents: minicdn::Base64Bytes::from_static(include_bytes!(#include_path)),
-                        #(#fields,)*
-                    });
+                #track
+
+                #[cfg(debug_assertions)]
+                {
+                    #path
                 }
-                .into(),
-            );
-        });
 
-    quote! {
-        {
-            let mut ret = minicdn::EmbeddedMiniCdn::default();
-            #(#files)*
-            ret
-        }
+                #[cfg(not(debug_assertions))]
+                {
+                    #served
+                }
+            }
+        },
+        Err(error) => error,
     }
     .into()
 }
 
-fn parse_arg(args: TokenStream) -> String {
-    let input = args.into_iter().collect::<Vec<_>>();
-    if input.len() != 1 {
-        panic!("expected exactly one input token, got {}", input.len());
+/// The contents of a module generated by [`include_mini_cdn_module!`].
+#[derive(Default)]
+struct Module {
+    /// Identifier to constant expression.
+    statics: BTreeMap<String, proc_macro2::TokenStream>,
+    /// Identifier to submodule.
+    modules: BTreeMap<String, Module>,
+    /// Identifier to the file or directory name it was derived from.
+    names: BTreeMap<String, String>,
+}
+
+impl Module {
+    /// Reserves an identifier for a file or directory name, failing if a different name already
+    /// has it (e.g. `logo.png` and `Logo.png`).
+    fn claim(&mut self, ident: &Ident, name: &str) -> Result<(), proc_macro2::TokenStream> {
+        match self.names.get(&ident.to_string()) {
+            Some(existing) if existing != name => {
+                let message = format!(
+                    "{:?} and {:?} both become the identifier {}, so rename one",
+                    existing, name, ident
+                );
+                Err(syn::Error::new(proc_macro2::Span::call_site(), message).to_compile_error())
+            }
+            _ => {
+                self.names.insert(ident.to_string(), name.to_owned());
+                Ok(())
+            }
+        }
+    }
+}
+
+impl ToTokens for Module {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        for (name, file) in &self.statics {
+            let name = Ident::new(name, proc_macro2::Span::call_site());
+            tokens.extend(quote! {
+                pub static #name: &minicdn::MiniCdnFile = {
+                    // A reference to a temporary wouldn't be allowed, due to interior mutability.
+                    static FILE: minicdn::MiniCdnFile = #file;
+                    &FILE
+                };
+            });
+        }
+        let blobs = Ident::new(Blobs::NAME, proc_macro2::Span::call_site());
+        for (name, module) in &self.modules {
+            let name = Ident::new(name, proc_macro2::Span::call_site());
+            tokens.extend(quote! {
+                pub mod #name {
+                    #[allow(unused_imports)]
+                    use super::#blobs;
+                    #module
+                }
+            });
+        }
+    }
+}
+
+/// Pairs the paths a file may be looked up by with the path it is served at: its path before
+/// fingerprinting and, if it was renamed, its source, unless that is ambiguous.
+fn asset_table(paths: &BTreeMap<String, CompressedPath>) -> BTreeMap<&str, &str> {
+    let mut table = paths
+        .iter()
+        .map(|(path, compressed)| (path.as_str(), compressed.served.as_str()))
+        .collect::<BTreeMap<_, _>>();
+    let mut sources = BTreeMap::<&str, Vec<&str>>::new();
+    for compressed in paths.values() {
+        sources
+            .entry(&compressed.source)
+            .or_default()
+            .push(&compressed.served);
+    }
+    for (source, served) in sources {
+        if let [served] = served[..] {
+            table.entry(source).or_insert(served);
+        }
+    }
+    table
+}
+
+/// Converts a file or directory name into an identifier, e.g. `logo.png` to `LOGO_PNG`.
+fn identifier(name: &str, upper_case: bool) -> Ident {
+    let mut identifier = name
+        .chars()
+        .map(|c| {
+            if !c.is_ascii_alphanumeric() {
+                '_'
+            } else if upper_case {
+                c.to_ascii_uppercase()
+            } else {
+                c.to_ascii_lowercase()
+            }
+        })
+        .collect::<String>();
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        identifier.insert(0, '_');
+    }
+    if syn::parse_str::<Ident>(&identifier).is_err() {
+        // Keyword.
+        identifier.push('_');
+    }
+    Ident::new(&identifier, proc_macro2::Span::call_site())
+}
+
+/// A module visibility and name, followed by [`Args`], e.g. `pub mod assets, "./public"`.
+struct ModuleArgs {
+    visibility: Visibility,
+    name: Ident,
+    args: Args,
+}
+
+impl Parse for ModuleArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let visibility = input.parse()?;
+        input.parse::<Token![mod]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![,]>()?;
+        Ok(Self {
+            visibility,
+            name,
+            args: input.parse()?,
+        })
+    }
+}
+
+/// A root path, optionally followed by the path of a file within it (for [`asset!`]), and
+/// then `key = value` options.
+struct Args {
+    path: String,
+    file: Option<LitStr>,
+    options: Vec<(Ident, Lit)>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse::<LitStr>()?.value();
+        let mut file = None;
+        let mut options = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            if file.is_none() && options.is_empty() && input.peek(LitStr) {
+                file = Some(input.parse::<LitStr>()?);
+                continue;
+            }
+            let key = input.parse::<Ident>()?;
+            input.parse::<Token![=]>()?;
+            options.push((key, input.parse::<Lit>()?));
+        }
+        Ok(Self {
+            path,
+            file,
+            options,
+        })
+    }
+}
+
+fn parse_args(args: TokenStream) -> Args {
+    match syn::parse::<Args>(args) {
+        Ok(args) => args,
+        Err(e) => panic!("error parsing arguments: {}", e),
     }
-    let string_lit = match StringLit::try_from(&input[0]) {
-        // Error if the token is not a string literal
-        Err(e) => panic!("error parsing argument: {:?}", e),
-        Ok(lit) => lit,
+}
+
+/// Resolves the [`asset!`] arguments to the paths of the file before and after fingerprinting,
+/// along with a statement that triggers a recompilation when the file changes.
+///
+/// The file may be given by the path it is served at, or by its source if it was renamed (e.g.
+/// `style.scss` for `style.css`).
+fn asset_paths(
+    args: TokenStream,
+) -> Result<(String, String, proc_macro2::TokenStream), proc_macro2::TokenStream> {
+    let args = parse_args(args);
+    let root_path = arg_to_path(&args.path);
+    let file = args
+        .file
+        .as_ref()
+        .unwrap_or_else(|| panic!("expected the path of a file after the root path"));
+    let original = file.value();
+
+    let mut paths = compressed_paths(&root_path, &args)?;
+    let (path, compressed) = if let Some(compressed) = paths.remove(&original) {
+        (original, compressed)
+    } else {
+        let mut renamed = paths
+            .into_iter()
+            .filter(|(_, compressed)| compressed.source == original)
+            .collect::<Vec<_>>();
+        match renamed.len() {
+            1 => renamed.remove(0),
+            0 => {
+                let message = format!("{:?} not found in {:?}", original, root_path);
+                return Err(syn::Error::new(file.span(), message).to_compile_error());
+            }
+            _ => {
+                let message = format!(
+                    "{:?} is served at multiple paths, so specify one of {:?}",
+                    original,
+                    renamed.iter().map(|(path, _)| path).collect::<Vec<_>>()
+                );
+                return Err(syn::Error::new(file.span(), message).to_compile_error());
+            }
+        }
+    };
+
+    // The file may have been compiled or generated, so track its source.
+    let include_path = Path::new(&root_path).join(&compressed.source);
+    let include_path = include_path
+        .to_str()
+        .expect("failed to stringify include path");
+
+    track(include_path);
+
+    let track = quote! {
+        const _: &[u8] = include_bytes!(#include_path);
     };
-    string_lit.value().to_string()
+
+    Ok((path, compressed.served, track))
+}
+
+/// [`EmbeddedMiniCdn::compressed_paths`], reused by later [`asset!`] calls in the same
+/// compilation with the same root path and options, unless a file changed in between.
+fn compressed_paths(
+    root_path: &str,
+    args: &Args,
+) -> Result<BTreeMap<String, CompressedPath>, proc_macro2::TokenStream> {
+    type Files = Vec<(String, Option<(u64, SystemTime)>)>;
+    static CACHE: Mutex<BTreeMap<String, (Files, BTreeMap<String, CompressedPath>)>> =
+        Mutex::new(BTreeMap::new());
+
+    let options = args
+        .options
+        .iter()
+        .map(|(key, value)| quote!(#key = #value));
+    let key = quote!(#root_path, #(#options),*).to_string();
+    let files = minicdn_core::source_paths(root_path)
+        .map(|path| {
+            let metadata = std::fs::metadata(&path).ok();
+            let state = metadata.and_then(|m| Some((m.len(), m.modified().ok()?)));
+            (path, state)
+        })
+        .collect::<Files>();
+
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((cached_files, paths)) = cache.get(&key) {
+        if *cached_files == files {
+            return Ok(paths.clone());
+        }
+    }
+    let options = compress_options(args);
+    let paths = catch_panic(|| EmbeddedMiniCdn::compressed_paths(root_path, &options))?;
+    cache.insert(key, (files, paths.clone()));
+    Ok(paths)
+}
+
+fn compress_options(args: &Args) -> CompressOptions {
+    #[allow(unused_mut)]
+    let mut options = CompressOptions::default().build_time();
+    for (key, value) in &args.options {
+        match (key.to_string().as_str(), value) {
+            #[cfg(feature = "fingerprint")]
+            ("fingerprint", Lit::Bool(value)) => options.fingerprint = value.value,
+            #[cfg(feature = "fingerprint")]
+            ("fingerprint_keep_original", Lit::Bool(value)) => {
+                options.fingerprint_keep_original = value.value
+            }
+            #[cfg(feature = "rewrite")]
+            ("fingerprint_rewrite", Lit::Bool(value)) => options.fingerprint_rewrite = value.value,
+            #[cfg(feature = "lazy")]
+            ("lazy", Lit::Bool(value)) => options.lazy = value.value,
+            #[cfg(any(feature = "brotli", feature = "gzip", feature = "webp"))]
+            ("compression_threshold", Lit::Float(value)) => {
+                options.compression_threshold = Some(compression_threshold(value.base10_parse()))
+            }
+            // A ratio, so `1` means the same as `1.0`.
+            #[cfg(any(feature = "brotli", feature = "gzip", feature = "webp"))]
+            ("compression_threshold", Lit::Int(value)) => {
+                options.compression_threshold = Some(compression_threshold(value.base10_parse()))
+            }
+            // A program, relative to the source file if it has a directory, or otherwise
+            // looked up in `PATH`. May be repeated.
+            #[cfg(feature = "transform")]
+            ("transform", Lit::Str(value)) => {
+                let program = value.value();
+                let program = if program.contains(std::path::is_separator) {
+                    arg_to_path(&program)
+                } else {
+                    program
+                };
+                options
+                    .transforms
+                    .push(std::sync::Arc::new(CommandTransform::new(program)));
+            }
+            _ => panic!("unexpected option {} (is the feature enabled?)", key),
+        }
+    }
+    options
+}
+
+#[cfg(any(feature = "brotli", feature = "gzip", feature = "webp"))]
+fn compression_threshold(value: syn::Result<f32>) -> f32 {
+    match value {
+        Ok(value) if (0.0..=1.0).contains(&value) => value,
+        Ok(value) => panic!(
+            "compression_threshold must be between 0 and 1, got {}",
+            value
+        ),
+        Err(e) => panic!("invalid compression_threshold: {}", e),
+    }
 }
 
 fn arg_to_path(arg: &str) -> String {
@@ -183,35 +705,17 @@ fn arg_to_path(arg: &str) -> String {
     }
 }
 
-#[derive(Debug)]
-struct ByteStr<'a>(pub &'a [u8]);
-
-impl<'a> ToTokens for ByteStr<'a> {
-    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
-        tokens.append(TokenTree::Literal(Literal::byte_string(self.0)));
-    }
-}
-
-#[allow(unused)]
-fn quote_bytes(data: &minicdn_core::Base64Bytes) -> proc_macro2::TokenStream {
-    let bytes = ByteStr(data.as_ref());
-    quote! {
-        minicdn::Base64Bytes::from_static(#bytes)
-    }
-    .into()
+/// Tells the compiler that the output depends on a file.
+#[allow(unused_variables)]
+fn track(path: &str) {
+    #[cfg(feature = "track_path")]
+    proc_macro::tracked::path(path);
 }
 
-#[allow(unused)]
-fn quote_option_bytes(opt: &Option<minicdn_core::Base64Bytes>) -> proc_macro2::TokenStream {
-    if let Some(data) = opt {
-        let bytes = quote_bytes(data);
-        quote! {
-            Some(#bytes)
-        }
-    } else {
-        quote! {
-            None
-        }
-        .into()
+/// Tells the user something about the output, e.g. how much deduplication saved, as a note
+/// attributed to the macro call, if they asked for it with `MINICDN_REPORT`.
+fn report(message: &str) {
+    if reporting() {
+        Diagnostic::spanned(proc_macro::Span::call_site(), Level::Note, message).emit();
     }
 }
@@ -1,11 +1,37 @@
 #[doc(hidden)]
+pub use minicdn_core::find_asset;
+#[cfg(feature = "phf")]
+#[doc(hidden)]
+pub use minicdn_core::phf;
+#[doc(hidden)]
 pub use minicdn_core::Base64Bytes;
+#[cfg(any(feature = "etag", feature = "last_modified", feature = "mime"))]
+pub use minicdn_core::ByteString;
+#[cfg(feature = "encoder")]
+pub use minicdn_core::Encoder;
 #[cfg(feature = "walkdir")]
 pub use minicdn_core::FilesystemMiniCdn;
-pub use minicdn_core::{EmbeddedMiniCdn, MiniCdn, MiniCdnFile};
-pub use minicdn_macros::include_mini_cdn;
+#[cfg(feature = "lazy")]
+pub use minicdn_core::Lazy;
+#[cfg(feature = "phf")]
+pub use minicdn_core::StaticMiniCdn;
+#[cfg(all(feature = "transform", feature = "config"))]
+pub use minicdn_core::TransformConfig;
+#[cfg(feature = "transform")]
+pub use minicdn_core::{CommandTransform, Transform, TransformInput, TransformOutput};
+pub use minicdn_core::{CompressOptions, EmbeddedMiniCdn, Encoding, MiniCdn, MiniCdnFile};
 #[cfg(feature = "walkdir")]
-pub use minicdn_macros::release_include_mini_cdn;
+pub use minicdn_core::{Compressed, CompressedPath};
+#[cfg(all(feature = "macros", feature = "phf"))]
+pub use minicdn_macros::include_mini_cdn_static;
+#[cfg(feature = "macros")]
+pub use minicdn_macros::{asset, include_mini_cdn, include_mini_cdn_module};
+#[cfg(all(feature = "macros", feature = "walkdir"))]
+pub use minicdn_macros::{release_asset, release_include_mini_cdn};
+
+// The macros refer to this crate by name, including from the modules they generate.
+#[cfg(test)]
+extern crate self as minicdn;
 
 #[cfg(test)]
 #[cfg(feature = "walkdir")]
@@ -13,6 +39,24 @@ mod tests {
     use minicdn_core::MiniCdn;
     use std::borrow::Cow;
 
+    /// A temporary directory containing `files` (path and contents), which is removed when
+    /// dropped, even if the test fails.
+    #[allow(dead_code)]
+    fn temp_tree(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            std::fs::write(dir.path().join(path), contents).unwrap();
+        }
+        dir
+    }
+
+    /// Runs `f`, which should panic, returning the message.
+    #[allow(dead_code)]
+    fn panic_message(f: impl FnOnce()) -> String {
+        let error = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err();
+        error.downcast_ref::<String>().unwrap().clone()
+    }
+
     #[test]
     fn simple() {
         simple_tests(MiniCdn::new_filesystem_from_path(Cow::Borrowed(
@@ -27,4 +71,1001 @@ mod tests {
             assert!(cdn.get("../include.rs").is_none());
         }
     }
+
+    #[test]
+    #[cfg(feature = "config")]
+    fn headers() {
+        headers_tests(MiniCdn::new_filesystem_from_path(Cow::Borrowed(
+            "examples/tree",
+        )));
+        headers_tests(MiniCdn::new_compressed_from_path("examples/tree"));
+
+        fn headers_tests(cdn: MiniCdn) {
+            let image = cdn.get("subtree/some_image_3.png").unwrap();
+            assert_eq!(
+                &*image.headers,
+                &[
+                    ("Access-Control-Allow-Origin".into(), "*".into()),
+                    ("Content-Disposition".into(), "attachment".into())
+                ]
+            );
+            assert!(cdn.get("index.html").unwrap().headers.is_empty());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "config")]
+    fn invalid_config() {
+        let dir = temp_tree(&[
+            ("file.txt", "text"),
+            ("file.minicdn", "headers = ["),
+            ("other.txt", "text"),
+        ]);
+        let cdn =
+            MiniCdn::new_filesystem_from_path(Cow::Owned(dir.path().to_str().unwrap().to_owned()));
+        // Treated as missing, rather than panicking in a server.
+        assert!(cdn.get("file.txt").is_none());
+        assert!(cdn.get("other.txt").is_some());
+        std::fs::write(dir.path().join(".minicdn"), "incompressible = [").unwrap();
+        assert!(cdn.get("other.txt").is_none());
+
+        // Headers that could break responses are rejected too.
+        for config in [
+            "[headers]\n\"X-Bad Name\" = \"value\"",
+            "[headers]\nX-Injected = \"value\\r\\nSet-Cookie: a=b\"",
+        ] {
+            let dir = temp_tree(&[("file.txt", "text"), ("file.minicdn", config)]);
+            let root_path = dir.path().to_str().unwrap();
+            let cdn = MiniCdn::new_filesystem_from_path(Cow::Owned(root_path.to_owned()));
+            assert!(cdn.get("file.txt").is_none());
+            let message = panic_message(|| {
+                MiniCdn::new_compressed_from_path(root_path);
+            });
+            assert!(message.contains("file.minicdn"), "{}", message);
+            assert!(message.contains("header"), "{}", message);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "fingerprint")]
+    fn fingerprint() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        let options = CompressOptions {
+            fingerprint: true,
+            ..Default::default()
+        };
+        let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        let fingerprinted = cdn.fingerprinted("subtree/some_binary.bin").unwrap();
+        assert!(fingerprinted.starts_with("subtree/some_binary."));
+        assert!(fingerprinted.ends_with(".bin"));
+        assert_eq!(fingerprinted.len(), "subtree/some_binary.bin".len() + 9);
+        assert!(cdn.get(fingerprinted).is_some());
+        assert!(cdn.get("subtree/some_binary.bin").is_none());
+
+        let options = CompressOptions {
+            fingerprint: true,
+            fingerprint_keep_original: true,
+            ..Default::default()
+        };
+        let cdn = MiniCdn::Embedded(EmbeddedMiniCdn::new_compressed_with_options(
+            "examples/tree",
+            &options,
+        ));
+        let fingerprinted = cdn.fingerprinted("subtree/some_binary.bin").unwrap();
+        assert_ne!(fingerprinted, "subtree/some_binary.bin");
+        assert!(cdn.get(fingerprinted).is_some());
+        assert!(cdn.get("subtree/some_binary.bin").is_some());
+
+        let cdn = MiniCdn::new_filesystem_from_path(Cow::Borrowed("examples/tree"));
+        assert_eq!(
+            cdn.fingerprinted("subtree/some_binary.bin"),
+            Some("subtree/some_binary.bin")
+        );
+        assert_eq!(cdn.fingerprinted("missing.bin"), None);
+        assert_eq!(cdn.fingerprinted("subtree"), None);
+        assert_eq!(cdn.fingerprinted("../include.rs"), None);
+    }
+
+    #[test]
+    fn compressed_paths() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        // Skips minification and such, but finds the same paths.
+        let options = CompressOptions::default();
+        let paths = EmbeddedMiniCdn::compressed_paths("examples/tree", &options);
+        let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        let mut served: Vec<_> = cdn.iter().map(|(path, _)| path.to_string()).collect();
+        served.sort();
+        assert_eq!(paths.keys().cloned().collect::<Vec<_>>(), served);
+        assert!(paths
+            .iter()
+            .all(|(path, compressed)| *path == compressed.served));
+    }
+
+    #[test]
+    #[cfg(feature = "macros")]
+    fn asset() {
+        use minicdn_macros::asset;
+
+        assert_eq!(asset!("../examples/tree", "index.html"), "index.html");
+        assert_eq!(
+            asset!("../examples/tree", "subtree/some_binary.bin"),
+            "subtree/some_binary.bin"
+        );
+
+        #[cfg(feature = "fingerprint")]
+        {
+            use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+            let options = CompressOptions {
+                fingerprint: true,
+                ..Default::default()
+            };
+            let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+            assert_eq!(
+                Some(asset!("../examples/tree", "style.css", fingerprint = true)),
+                cdn.fingerprinted("style.css")
+            );
+            assert_eq!(
+                asset!("../examples/tree", "index.html", fingerprint = true),
+                "index.html"
+            );
+        }
+
+        #[cfg(feature = "sass")]
+        {
+            assert_eq!(asset!("../examples/tree", "theme.scss"), "theme.css");
+            assert_eq!(asset!("../examples/tree", "theme.css"), "theme.css");
+        }
+
+        #[cfg(feature = "markdown")]
+        {
+            assert_eq!(asset!("../examples/tree", "guide.md"), "guide.md");
+            assert_eq!(asset!("../examples/tree", "guide.html"), "guide.html");
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "macros", feature = "fingerprint"))]
+    fn module_asset() {
+        minicdn_macros::include_mini_cdn_module!(mod tree, "../examples/tree", fingerprint = true);
+
+        const INDEX: &str = tree::asset("index.html");
+        assert_eq!(INDEX, "index.html");
+        let style = tree::asset("style.css");
+        assert_ne!(style, "style.css");
+        assert_eq!(tree::mini_cdn().fingerprinted("style.css"), Some(style));
+        #[cfg(feature = "sass")]
+        assert_eq!(tree::asset("theme.scss"), tree::asset("theme.css"));
+        assert!(std::panic::catch_unwind(|| tree::asset("missing.txt")).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "macros", feature = "phf"))]
+    fn include_static() {
+        use minicdn_core::{Encoding, StaticMiniCdn};
+        use minicdn_macros::{include_mini_cdn, include_mini_cdn_static};
+
+        static CDN: &StaticMiniCdn = include_mini_cdn_static!("../examples/tree");
+        let embedded = include_mini_cdn!("../examples/tree");
+
+        let mut paths = CDN.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        paths.sort_unstable();
+        let mut expected = embedded.iter().map(|(path, _)| &**path).collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(paths, expected);
+
+        // Every path hashes to its own file.
+        for (path, file) in CDN.iter() {
+            let expected = embedded.get(path).unwrap();
+            assert!(std::ptr::eq(CDN.get(path).unwrap(), file), "{}", path);
+            assert_eq!(file.contents(), expected.contents(), "{}", path);
+            assert_eq!(file.etag(), expected.etag(), "{}", path);
+            assert_eq!(file.mime(), expected.mime(), "{}", path);
+            for encoding in [Encoding::Brotli, Encoding::Gzip, Encoding::Webp] {
+                assert_eq!(
+                    file.encoded(encoding),
+                    expected.encoded(encoding),
+                    "{}",
+                    path
+                );
+            }
+        }
+        assert!(CDN.get("missing.txt").is_none());
+        assert!(CDN.get("/index.html").is_none());
+        assert!(CDN.get("").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "macros")]
+    fn dedup() {
+        use minicdn_macros::include_mini_cdn;
+
+        // The images all have the same contents, so share a blob.
+        let cdn = include_mini_cdn!("../examples/tree");
+        let image = cdn
+            .get("subtree/some_image.png")
+            .unwrap()
+            .contents()
+            .as_ptr();
+        for path in ["subtree/some_image_2.png", "subtree/some_image_4.png"] {
+            assert_eq!(
+                cdn.get(path).unwrap().contents().as_ptr(),
+                image,
+                "{}",
+                path
+            );
+        }
+        assert_ne!(
+            cdn.get("subtree/some_binary.bin")
+                .unwrap()
+                .contents()
+                .as_ptr(),
+            image
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rewrite")]
+    fn rewrite() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        let options = CompressOptions {
+            fingerprint: true,
+            fingerprint_rewrite: true,
+            ..Default::default()
+        };
+        let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        assert_eq!(cdn.fingerprinted("index.html"), None);
+
+        let style_path = cdn.fingerprinted("style.css").unwrap();
+        let image_path = cdn.fingerprinted("subtree/some_image.png").unwrap();
+        let style = std::str::from_utf8(&cdn.get(style_path).unwrap().contents).unwrap();
+        // Minifying drops the quotes.
+        assert!(
+            style.contains(&format!("url(\"{}\")", image_path))
+                || style.contains(&format!("url({})", image_path)),
+            "{}",
+            style
+        );
+
+        let index = std::str::from_utf8(&cdn.get("index.html").unwrap().contents).unwrap();
+        assert!(
+            index.contains(&format!("href=\"{}\"", style_path)),
+            "{}",
+            index
+        );
+        assert!(
+            index.contains(&format!(
+                "src=\"{}\"",
+                cdn.fingerprinted("subtree/some_image_2.png").unwrap()
+            )),
+            "{}",
+            index
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rewrite")]
+    fn rewrite_cycle() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        let dir = temp_tree(&[
+            ("a.css", "a { background: url(\"b.css\") }"),
+            ("b.css", "b { background: url(\"a.css\") }"),
+            ("c.css", "c { background: url(\"a.css\") }"),
+            ("d.css", "d { background: url(\"c.css\") }"),
+        ]);
+        let options = CompressOptions {
+            fingerprint: true,
+            fingerprint_rewrite: true,
+            ..Default::default()
+        };
+        let cdn =
+            EmbeddedMiniCdn::new_compressed_with_options(dir.path().to_str().unwrap(), &options);
+
+        // Each file in the cycle would depend on its own fingerprint.
+        assert_eq!(cdn.fingerprinted("a.css"), None);
+        assert_eq!(cdn.fingerprinted("b.css"), None);
+        let c = cdn.fingerprinted("c.css").unwrap();
+        let c_contents = std::str::from_utf8(&cdn.get(c).unwrap().contents).unwrap();
+        assert!(c_contents.contains("a.css"), "{}", c_contents);
+        let d = cdn.fingerprinted("d.css").unwrap();
+        let d_contents = std::str::from_utf8(&cdn.get(d).unwrap().contents).unwrap();
+        assert!(d_contents.contains(c), "{}", d_contents);
+    }
+
+    #[test]
+    #[cfg(all(feature = "cache", feature = "gzip"))]
+    fn cache() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        let dir = temp_tree(&[]);
+        let cache_dir = dir.path().join("cache");
+        let options = CompressOptions {
+            cache_dir: Some(cache_dir.clone()),
+            ..Default::default()
+        };
+        // Left by another version, which is removed, unlike anything else in the directory.
+        let stale = cache_dir.join("v0123456789abcdef");
+        let other = cache_dir.join("other");
+        std::fs::create_dir_all(&stale).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+
+        let first = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        assert!(!stale.exists());
+        assert!(other.is_dir());
+        let second = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        for (path, file) in first.iter() {
+            assert_eq!(
+                file.contents_gzip,
+                second.get(path).unwrap().contents_gzip,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lazy")]
+    fn lazy() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        let eager = EmbeddedMiniCdn::new_compressed("examples/tree");
+        let options = CompressOptions {
+            lazy: true,
+            ..Default::default()
+        };
+        let lazy = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+
+        let text = lazy.get("lorem_ipsum.txt").unwrap();
+        assert!(text.lazy.is_omitted());
+        assert!(text.contents.is_empty());
+        // Compressed with zopfli, so not reproducible from the level.
+        #[cfg(feature = "zopfli")]
+        assert!(text.contents_gzip.is_some());
+
+        // Stays lazy when serialized.
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(text).unwrap();
+            let text: minicdn_core::MiniCdnFile = serde_json::from_str(&json).unwrap();
+            assert!(text.contents.is_empty());
+            assert!(text.contents().starts_with(b"Lorem ipsum"));
+        }
+
+        for (path, file) in eager.iter() {
+            let lazy_file = lazy.get(path).unwrap();
+            assert_eq!(file.contents(), lazy_file.contents(), "{}", path);
+            #[cfg(feature = "gzip")]
+            assert_eq!(file.contents_gzip(), lazy_file.contents_gzip(), "{}", path);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", any(feature = "brotli", feature = "gzip")))]
+    fn auto() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        // Reported the same way, with the same output, whether or not it was cached.
+        let index = std::fs::read_to_string("examples/tree/index.html").unwrap();
+        let tree = temp_tree(&[
+            ("index.html", &index),
+            ("index.minicdn", "auto = true\nauto_candidates = 3"),
+        ]);
+        let tree = tree.path().to_str().unwrap();
+        let dir = temp_tree(&[]);
+        let options = CompressOptions {
+            #[cfg(feature = "cache")]
+            cache_dir: Some(dir.path().join("cache")),
+            ..Default::default()
+        };
+        let minicdn_core::Compressed {
+            mini_cdn: first,
+            report: first_report,
+            ..
+        } = EmbeddedMiniCdn::compress(tree, &options);
+        let minicdn_core::Compressed {
+            mini_cdn: second,
+            report: second_report,
+            ..
+        } = EmbeddedMiniCdn::compress(tree, &options);
+        assert_eq!(first_report, second_report);
+        let report = first_report
+            .iter()
+            .find(|message| message.starts_with("minicdn: index.html: "))
+            .unwrap();
+        #[cfg(feature = "brotli")]
+        {
+            assert!(report.contains("brotli_level = "), "{}", report);
+            assert_eq!(
+                first.get("index.html").unwrap().contents_brotli,
+                second.get("index.html").unwrap().contents_brotli
+            );
+        }
+        #[cfg(feature = "gzip")]
+        {
+            assert!(report.contains("gzip_level = "), "{}", report);
+            assert_eq!(
+                first.get("index.html").unwrap().contents_gzip,
+                second.get("index.html").unwrap().contents_gzip
+            );
+        }
+
+        // With no time, only the configured parameters are tried.
+        let contents = "budget ".repeat(1000);
+        let dir = temp_tree(&[
+            ("file.txt", &contents),
+            (
+                "file.minicdn",
+                "auto = true\nauto_budget_ms = 0\ngzip_level = 7",
+            ),
+        ]);
+        let report = EmbeddedMiniCdn::compress(dir.path().to_str().unwrap(), &options).report;
+        #[cfg(feature = "brotli")]
+        assert!(
+            report[0].contains("brotli_level = 9, brotli_large_window_size = 20"),
+            "{:?}",
+            report
+        );
+        #[cfg(feature = "gzip")]
+        assert!(report[0].contains("gzip_level = 7"), "{:?}", report);
+    }
+
+    #[test]
+    #[cfg(feature = "zopfli")]
+    fn zopfli() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        let file = cdn.get("lorem_ipsum.txt").unwrap();
+        let gzip = file.contents_gzip.as_ref().unwrap();
+        // GZIP magic number.
+        assert_eq!(gzip[..2], [0x1f, 0x8b]);
+        assert!(gzip.len() < file.contents.len() / 2);
+    }
+
+    #[test]
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    fn compression_threshold() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn};
+
+        let options = CompressOptions {
+            compression_threshold: Some(0.0),
+            ..Default::default()
+        };
+        let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        let file = cdn.get("lorem_ipsum.txt").unwrap();
+        #[cfg(feature = "brotli")]
+        assert!(file.contents_brotli.is_none());
+        #[cfg(feature = "gzip")]
+        assert!(file.contents_gzip.is_none());
+
+        let options = CompressOptions {
+            compression_threshold: Some(1.0),
+            ..Default::default()
+        };
+        let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        let file = cdn.get("lorem_ipsum.txt").unwrap();
+        #[cfg(feature = "brotli")]
+        assert!(file.contents_brotli.is_some());
+        #[cfg(feature = "gzip")]
+        assert!(file.contents_gzip.is_some());
+
+        // Thresholds outside 0..=1 are rejected, naming where they came from.
+        let options = CompressOptions {
+            compression_threshold: Some(1.5),
+            ..Default::default()
+        };
+        let message = panic_message(|| {
+            EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        });
+        assert!(
+            message.contains("CompressOptions::compression_threshold"),
+            "{}",
+            message
+        );
+        #[cfg(feature = "config")]
+        {
+            let dir = temp_tree(&[
+                ("file.txt", "text"),
+                ("file.minicdn", "compression_threshold = -1"),
+            ]);
+            let message = panic_message(|| {
+                EmbeddedMiniCdn::new_compressed(dir.path().to_str().unwrap());
+            });
+            assert!(message.contains("file.minicdn"), "{}", message);
+            assert!(message.contains("between 0 and 1"), "{}", message);
+        }
+
+        // The macros accept integers too.
+        #[cfg(feature = "macros")]
+        {
+            let cdn =
+                minicdn_macros::include_mini_cdn!("../examples/tree", compression_threshold = 0);
+            let file = cdn.get("lorem_ipsum.txt").unwrap();
+            #[cfg(feature = "brotli")]
+            assert!(file.contents_brotli.is_none());
+            #[cfg(feature = "gzip")]
+            assert!(file.contents_gzip.is_none());
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", feature = "webp"))]
+    fn variant_opt_out() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        assert!(cdn
+            .get("subtree/some_image.png")
+            .unwrap()
+            .contents_webp
+            .is_some());
+        assert!(cdn
+            .get("subtree/some_image_4.png")
+            .unwrap()
+            .contents_webp
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(any(feature = "brotli", feature = "gzip"))]
+    fn incompressible() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        for (path, compressed) in [("subtree/some_image_3.png", false), ("index.html", true)] {
+            let file = cdn.get(path).unwrap();
+            #[cfg(feature = "brotli")]
+            assert_eq!(file.contents_brotli.is_some(), compressed, "{}", path);
+            #[cfg(feature = "gzip")]
+            assert_eq!(file.contents_gzip.is_some(), compressed, "{}", path);
+        }
+
+        // An explicit per-file setting overrides the root config.
+        #[cfg(feature = "config")]
+        {
+            let contents = "not really a PNG ".repeat(100);
+            let dir = temp_tree(&[
+                ("image.png", &contents),
+                ("forced.png", &contents),
+                ("forced.minicdn", "brotli = true\ngzip = true"),
+                ("text.txt", &contents),
+                ("text.minicdn", "brotli = false\ngzip = false"),
+            ]);
+            let cdn = MiniCdn::new_compressed_from_path(dir.path().to_str().unwrap());
+            for (path, compressed) in [
+                ("image.png", false),
+                ("forced.png", true),
+                ("text.txt", false),
+            ] {
+                let file = cdn.get(path).unwrap();
+                #[cfg(feature = "brotli")]
+                assert_eq!(file.contents_brotli.is_some(), compressed, "{}", path);
+                #[cfg(feature = "gzip")]
+                assert_eq!(file.contents_gzip.is_some(), compressed, "{}", path);
+            }
+        }
+
+        // Generated files are judged by their own MIME type.
+        #[cfg(feature = "transform")]
+        {
+            use minicdn_core::{
+                CompressOptions, EmbeddedMiniCdn, Transform, TransformInput, TransformOutput,
+            };
+
+            /// Generates a compressible PNG next to each text file.
+            #[derive(Debug)]
+            struct Thumbnail;
+
+            impl Transform for Thumbnail {
+                fn transform(&self, input: TransformInput<'_>) -> TransformOutput {
+                    let thumbnail = format!("{}.png", input.relative_path);
+                    let mut output = input.unchanged();
+                    output
+                        .extra_files
+                        .push((thumbnail, "not really a PNG ".repeat(100).into_bytes()));
+                    output
+                }
+            }
+
+            let dir = temp_tree(&[("text.txt", &"some text ".repeat(100))]);
+            let options = CompressOptions {
+                transforms: vec![std::sync::Arc::new(Thumbnail)],
+                ..Default::default()
+            };
+            let cdn = EmbeddedMiniCdn::new_compressed_with_options(
+                dir.path().to_str().unwrap(),
+                &options,
+            );
+            for (path, compressed) in [("text.txt", true), ("text.txt.png", false)] {
+                let file = cdn.get(path).unwrap();
+                #[cfg(feature = "brotli")]
+                assert_eq!(file.contents_brotli.is_some(), compressed, "{}", path);
+                #[cfg(feature = "gzip")]
+                assert_eq!(file.contents_gzip.is_some(), compressed, "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", feature = "minify_html"))]
+    fn minify_html() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        for (path, minified) in [("index.html", true), ("subtree/verbatim.html", false)] {
+            let original = std::fs::read(format!("examples/tree/{}", path)).unwrap();
+            let contents = &cdn.get(path).unwrap().contents;
+            assert_eq!(contents.len() < original.len(), minified, "{}", path);
+            assert_eq!(contents.as_ref() == original, !minified, "{}", path);
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", feature = "minify_css"))]
+    fn minify_css() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        let style = cdn.get("style.css").unwrap();
+        let style = std::str::from_utf8(&style.contents).unwrap();
+        // Imported, minified and lowered for Safari 13 (see examples/tree/.minicdn).
+        assert!(!style.contains("@import"), "{}", style);
+        assert!(style.contains("margin:0"), "{}", style);
+        assert!(style.contains("-webkit-user-select:none"), "{}", style);
+        assert!(!style.contains("Hello"), "{}", style);
+
+        // Browserslist queries work too.
+        let dir = temp_tree(&[
+            ("style.css", "a { user-select: none }"),
+            (".minicdn", "css_targets = \"> 0.5%, last 2 versions\""),
+        ]);
+        let cdn = MiniCdn::new_compressed_from_path(dir.path().to_str().unwrap());
+        let style = cdn.get("style.css").unwrap();
+        let style = std::str::from_utf8(&style.contents).unwrap();
+        assert!(style.contains("-webkit-user-select:none"), "{}", style);
+
+        // Invalid ones are reported with the config file.
+        std::fs::write(
+            dir.path().join(".minicdn"),
+            "css_targets = [\"not a query\"]",
+        )
+        .unwrap();
+        let message = panic_message(|| {
+            MiniCdn::new_compressed_from_path(dir.path().to_str().unwrap());
+        });
+        assert!(
+            message.contains("invalid browserslist query"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", feature = "minify_js"))]
+    fn minify_js() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        let script = cdn.get("script.js").unwrap();
+        let script = std::str::from_utf8(&script.contents).unwrap();
+        assert!(
+            script.starts_with("/*! script.js | MIT License */\n"),
+            "{}",
+            script
+        );
+        assert!(!script.contains("Greets"), "{}", script);
+        assert!(
+            script.ends_with("//# sourceMappingURL=script.js.map\n"),
+            "{}",
+            script
+        );
+
+        let source_map = cdn.get("script.js.map").unwrap();
+        let source_map: serde_json::Value = serde_json::from_slice(&source_map.contents).unwrap();
+        assert_eq!(source_map["sources"][0], "script.js");
+        assert_eq!(
+            source_map["sourcesContent"][0].as_str().map(str::as_bytes),
+            Some(&*std::fs::read("examples/tree/script.js").unwrap())
+        );
+        // Maps more than the start of the file.
+        assert!(
+            source_map["mappings"].as_str().unwrap().contains(','),
+            "{}",
+            source_map
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "minify_json", feature = "minify_svg"))]
+    fn minify_json_svg() {
+        let cdn = MiniCdn::new_compressed_from_path("examples/tree");
+        let data = cdn.get("data.json").unwrap();
+        assert_eq!(
+            std::str::from_utf8(&data.contents).unwrap(),
+            r#"{"name":"minicdn","version":1.50,"tags":["static","compressed"],"nested":{"note":"spaces \" inside strings are kept"}}"#
+        );
+
+        let icon = cdn.get("subtree/icon.svg").unwrap();
+        assert_eq!(
+            std::str::from_utf8(&icon.contents).unwrap(),
+            concat!(
+                r#"<svg width="16" height="16" viewBox="0 0 16 16" xmlns="http://www.w3.org/2000/svg">"#,
+                r#"<title>Icon</title>"#,
+                r##"<circle cx="8" cy="8" r="7" fill="#336699"/>"##,
+                r#"<text x="2" y="12"><tspan>A</tspan> <tspan>B</tspan></text></svg>"#,
+            )
+        );
+
+        // Inkscape declares its namespaces after using them, and prefixes are scoped to the
+        // element declaring them.
+        let drawing = cdn.get("subtree/drawing.svg").unwrap();
+        let drawing = std::str::from_utf8(&drawing.contents).unwrap();
+        roxmltree::Document::parse(drawing).unwrap_or_else(|e| panic!("{}: {}", e, drawing));
+        assert_eq!(
+            drawing,
+            concat!(
+                r#"<svg width="16" height="16" xmlns="http://www.w3.org/2000/svg" xmlns:svg="http://www.w3.org/2000/svg">"#,
+                r#"<g><rect width="8" height="8"/></g>"#,
+                r#"<g xmlns:sketch="http://example.com/not-an-editor"><rect x="8" width="8" height="8" sketch:type="kept"/></g>"#,
+                r#"</svg>"#,
+            )
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", feature = "transform"))]
+    fn transform() {
+        use minicdn_core::{
+            CompressOptions, EmbeddedMiniCdn, Transform, TransformInput, TransformOutput,
+        };
+
+        /// Prepends the configured banner, and moves the file to `renamed.txt`.
+        #[derive(Debug)]
+        struct Banner;
+
+        impl Transform for Banner {
+            fn transform(&self, input: TransformInput<'_>) -> TransformOutput {
+                let Some(banner) = input.config.get_str("banner") else {
+                    return input.unchanged();
+                };
+                assert_eq!(input.mime, "text/plain");
+                let mut contents = banner.as_bytes().to_vec();
+                contents.extend_from_slice(&input.contents);
+                TransformOutput {
+                    relative_path: "renamed.txt".to_owned(),
+                    extra_files: vec![(
+                        format!("{}.len", input.relative_path),
+                        contents.len().to_string().into_bytes(),
+                    )],
+                    contents,
+                }
+            }
+        }
+
+        let options = CompressOptions {
+            transforms: vec![std::sync::Arc::new(Banner)],
+            ..Default::default()
+        };
+        let dir = temp_tree(&[
+            ("some_text.txt", "this is some text"),
+            ("some_text.minicdn", "banner = \"Banner: \""),
+            ("other.txt", "no banner"),
+        ]);
+        let cdn =
+            EmbeddedMiniCdn::new_compressed_with_options(dir.path().to_str().unwrap(), &options);
+        assert_eq!(&*cdn.get("other.txt").unwrap().contents, b"no banner");
+        assert!(cdn.get("some_text.txt").is_none());
+        let renamed = cdn.get("renamed.txt").unwrap();
+        assert_eq!(&*renamed.contents, b"Banner: this is some text");
+        assert_eq!(&*cdn.get("some_text.txt.len").unwrap().contents, b"25");
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "transform"))]
+    fn command_transform() {
+        use minicdn_core::{CommandTransform, CompressOptions, EmbeddedMiniCdn};
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = temp_tree(&[
+            ("page.txt", "<p>hello</p>"),
+            (
+                "upper.sh",
+                "#!/bin/sh\nprintf '%s:' \"$MINICDN_RELATIVE_PATH\"\ntr a-z A-Z\n",
+            ),
+            ("fail.sh", "#!/bin/sh\necho 'no good' >&2\nexit 3\n"),
+        ]);
+        for script in ["upper.sh", "fail.sh"] {
+            let path = dir.path().join(script);
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let options = CompressOptions {
+            transforms: vec![std::sync::Arc::new(CommandTransform::new(
+                dir.path().join("upper.sh"),
+            ))],
+            ..Default::default()
+        };
+        let cdn =
+            EmbeddedMiniCdn::new_compressed_with_options(dir.path().to_str().unwrap(), &options);
+        assert_eq!(
+            &*cdn.get("page.txt").unwrap().contents,
+            b"page.txt:<P>HELLO</P>"
+        );
+
+        let options = CompressOptions {
+            transforms: vec![std::sync::Arc::new(CommandTransform::new(
+                dir.path().join("fail.sh"),
+            ))],
+            ..Default::default()
+        };
+        let message = panic_message(|| {
+            EmbeddedMiniCdn::new_compressed_with_options(dir.path().to_str().unwrap(), &options);
+        });
+        assert!(message.contains("no good"), "{}", message);
+
+        // The macros run programs too, here from `PATH`.
+        #[cfg(feature = "macros")]
+        {
+            let cdn = minicdn_macros::include_mini_cdn!("../examples/tree", transform = "cat");
+            assert_eq!(
+                &*cdn.get("some_text.txt").unwrap().contents,
+                b"this is some text"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "encoder")]
+    fn encoder() {
+        use minicdn_core::{CompressOptions, EmbeddedMiniCdn, Encoder};
+
+        /// Keeps the first half of text files.
+        #[derive(Debug)]
+        struct Half;
+
+        impl Encoder for Half {
+            fn key(&self) -> &str {
+                "x-half"
+            }
+
+            fn encode(&self, mime: &str, contents: &[u8]) -> Option<Vec<u8>> {
+                (mime == "text/plain").then(|| contents[..contents.len() / 2].to_vec())
+            }
+        }
+
+        let options = CompressOptions {
+            encoders: vec![std::sync::Arc::new(Half)],
+            ..Default::default()
+        };
+        let cdn = EmbeddedMiniCdn::new_compressed_with_options("examples/tree", &options);
+        let text = cdn.get("some_text.txt").unwrap();
+        let (key, half) = text.variants().find(|(key, _)| *key == "x-half").unwrap();
+        assert_eq!(key, "x-half");
+        assert_eq!(&**half, &text.contents[..text.contents.len() / 2]);
+        assert!(cdn
+            .get("style.css")
+            .unwrap()
+            .variants()
+            .all(|(key, _)| key != "x-half"));
+    }
+
+    #[test]
+    fn accessors() {
+        use minicdn_core::{EmbeddedMiniCdn, Encoding};
+
+        let cdn = EmbeddedMiniCdn::new_compressed("examples/tree");
+        let text = cdn.get("lorem_ipsum.txt").unwrap();
+        assert_eq!(text.etag().is_some(), cfg!(feature = "etag"));
+        assert_eq!(
+            text.last_modified().is_some(),
+            cfg!(feature = "last_modified")
+        );
+        assert_eq!(text.mime(), cfg!(feature = "mime").then_some("text/plain"));
+        assert_eq!(
+            text.encoded(Encoding::Brotli).is_some(),
+            cfg!(feature = "brotli")
+        );
+        assert_eq!(
+            text.encoded(Encoding::Gzip).is_some(),
+            cfg!(feature = "gzip")
+        );
+        assert!(text.encoded(Encoding::Webp).is_none());
+        assert_eq!(text.headers().count(), 0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "config", feature = "markdown"))]
+    fn markdown() {
+        use minicdn_core::{EmbeddedMiniCdn, FilesystemMiniCdn};
+
+        let cdn = EmbeddedMiniCdn::new_compressed("examples/tree");
+        assert!(cdn.get("_layout.html").is_none());
+        assert!(cdn.get("guide.md").is_some());
+        let guide = cdn.get("guide.html").unwrap();
+        let html = String::from_utf8_lossy(&guide.contents);
+        assert!(html.contains("<title>Getting started</title>"), "{}", html);
+        assert!(html.contains("<h1>Getting started</h1>"), "{}", html);
+        assert!(html.contains("guide.html#usage"), "{}", html);
+        assert!(html.contains("some_text.txt"), "{}", html);
+        assert!(html.contains("<table>"), "{}", html);
+        assert!(!html.contains("+++"), "{}", html);
+
+        // Rendered in debug mode too.
+        let filesystem = FilesystemMiniCdn::new(Cow::Borrowed("examples/tree"));
+        assert!(filesystem.get("_layout.html").is_none());
+        assert!(filesystem.get("guide.md").is_some());
+        let guide = filesystem.get("guide.html").unwrap();
+        let html = String::from_utf8_lossy(&guide.contents);
+        assert!(html.contains("<h1>Getting started</h1>"), "{}", html);
+        let paths = filesystem.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert!(paths.contains(&"guide.html".to_owned()), "{:?}", paths);
+        assert!(!paths.contains(&"_layout.html".to_owned()), "{:?}", paths);
+
+        // Errors panic at compile time, but a server shouldn't crash, so the file is missing.
+        let dir = temp_tree(&[("page.md", "+++\ntitle = \n+++\n# Page\n")]);
+        let message = panic_message(|| {
+            EmbeddedMiniCdn::new_compressed(dir.path().to_str().unwrap());
+        });
+        assert!(
+            message.contains("page.md: invalid front matter"),
+            "{}",
+            message
+        );
+        let yaml = temp_tree(&[("page.md", "---\ntitle: Page\n---\n# Page\n")]);
+        let message = panic_message(|| {
+            EmbeddedMiniCdn::new_compressed(yaml.path().to_str().unwrap());
+        });
+        assert!(
+            message.contains("page.md: YAML front matter"),
+            "{}",
+            message
+        );
+        let filesystem =
+            FilesystemMiniCdn::new(Cow::Owned(dir.path().to_str().unwrap().to_owned()));
+        assert!(filesystem.get("page.html").is_none());
+        assert!(filesystem.get("page.md").is_some());
+
+        let dir = temp_tree(&[
+            ("page.md", "# Page\n"),
+            (".minicdn", "markdown_layout = \"missing.html\""),
+        ]);
+        let filesystem =
+            FilesystemMiniCdn::new(Cow::Owned(dir.path().to_str().unwrap().to_owned()));
+        assert!(filesystem.get("page.html").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "sass")]
+    fn sass() {
+        use minicdn_core::{EmbeddedMiniCdn, FilesystemMiniCdn};
+
+        let cdn = EmbeddedMiniCdn::new_compressed("examples/tree");
+        assert!(cdn.get("theme.scss").is_none());
+        assert!(cdn.get("_colors.scss").is_none());
+        let theme = cdn.get("theme.css").unwrap();
+        let css = String::from_utf8_lossy(&theme.contents);
+        assert!(css.contains("nav a"), "{}", css);
+        assert!(css.contains("#336699") || css.contains("#369"), "{}", css);
+
+        // Compiled in debug mode too.
+        let filesystem = FilesystemMiniCdn::new(Cow::Borrowed("examples/tree"));
+        assert!(filesystem.get("theme.scss").is_none());
+        assert!(filesystem.get("_colors.scss").is_none());
+        assert!(filesystem.get("_colors.css").is_none());
+        let theme = filesystem.get("theme.css").unwrap();
+        let css = String::from_utf8_lossy(&theme.contents);
+        assert!(css.contains("nav a"), "{}", css);
+        let paths = filesystem.iter().map(|(path, _)| path).collect::<Vec<_>>();
+        assert!(paths.contains(&"theme.css".to_owned()), "{:?}", paths);
+        assert!(
+            !paths.iter().any(|path| path.ends_with(".scss")),
+            "{:?}",
+            paths
+        );
+
+        let dir = temp_tree(&[("broken.scss", "a {\n  color: $missing;\n}\n")]);
+        let message = panic_message(|| {
+            EmbeddedMiniCdn::new_compressed(dir.path().to_str().unwrap());
+        });
+        assert!(
+            message.contains("broken.scss:2:10: Undefined variable."),
+            "{}",
+            message
+        );
+        // A server shouldn't crash, so the file is missing instead.
+        let filesystem =
+            FilesystemMiniCdn::new(Cow::Owned(dir.path().to_str().unwrap().to_owned()));
+        assert!(filesystem.get("broken.css").is_none());
+    }
 }